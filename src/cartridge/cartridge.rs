@@ -1,13 +1,15 @@
 use crate::cartridge::mbc1::MBC1;
 use crate::cartridge::rom_only::RomOnly;
 use crate::mmu::memory::Memory;
+use crate::state::SaveState;
 
 pub enum CartridgeType {
     RomOnly,
     MBC1,
 }
 
-pub trait Cartridge: Memory {}
+// save states cover the mapper registers and RAM, the ROM itself is not saved
+pub trait Cartridge: Memory + SaveState {}
 
 impl CartridgeType {
     pub fn load(&self, v: Vec<u8>) -> Box<dyn Cartridge> {
@@ -1,5 +1,6 @@
 use crate::cartridge::cartridge::Cartridge;
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub struct MBC1 {
     rom: Vec<u8>,
@@ -89,4 +90,23 @@ impl Memory for MBC1 {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for MBC1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.bool(self.ramg);
+        w.bool(self.mode);
+        w.u8(self.bank1_reg);
+        w.u8(self.bank2_reg);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.ram)?;
+        self.ramg = r.bool()?;
+        self.mode = r.bool()?;
+        self.bank1_reg = r.u8()?;
+        self.bank2_reg = r.u8()?;
+        Ok(())
+    }
+}
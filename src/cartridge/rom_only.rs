@@ -1,5 +1,6 @@
 use crate::cartridge::cartridge::Cartridge;
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub struct RomOnly {
     bank: [u8; 0x8000],
@@ -23,4 +24,13 @@ impl Memory for RomOnly {
     fn set_byte(&mut self, address: u16, value: u8) {
         println!("trying to write 0x{:X?} to address 0x{:X?}", value, address);
     }
-}
\ No newline at end of file
+}
+
+// nothing but ROM
+impl SaveState for RomOnly {
+    fn save_state(&self, _: &mut StateWriter) {}
+
+    fn load_state(&mut self, _: &mut StateReader) -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -1,14 +1,16 @@
 use crate::cpu::opcode::Opcode;
-use crate::cpu::registers::{F, Registers, RR};
+use crate::cpu::registers::{Registers, RR};
+use crate::cpu::trace::Tracer;
 use crate::mmu::bus::Bus;
-use crate::mmu::memory::Memory;
+use crate::model::Model;
 use crate::ppu::ppu::Ppu;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 static IE: u16 = 0xFFFF;
 static IF: u16 = 0xFF0F;
-static DMA: u16 = 0xFF46;
 
 pub struct Cpu {
+    pub model: Model,
     pub registers: Registers,
     pub ime: bool,
     pub ime_delay: bool,
@@ -16,11 +18,19 @@ pub struct Cpu {
     tima_ctr: u16,
     tma_flag: bool,
     pub halted: bool,
+    // M-cycles left without running, timers included, after a speed switch
+    pub stall: u16,
+    pub tracer: Option<Tracer>,
 }
 
 impl Cpu {
-    pub fn new() -> Self {
-        Self { registers: Default::default(), ime: false, ime_delay: false, tma_flag: false, waiting_for_interrupt: false,  tima_ctr: 0 , halted: false}
+    pub fn new(model: Model) -> Self {
+        Self { model, registers: Default::default(), ime: false, ime_delay: false, tma_flag: false, waiting_for_interrupt: false,  tima_ctr: 0 , halted: false, stall: 0, tracer: None }
+    }
+
+    // registers the boot ROM of this model leaves for the cartridge, see Registers::post_boot
+    pub fn skip_boot(&mut self, header_checksum: u8) {
+        self.registers = Registers::post_boot(self.model, header_checksum);
     }
 
     pub fn tick(&mut self, bus: &mut Bus, steps: i32) -> i32 {
@@ -28,12 +38,23 @@ impl Cpu {
             return steps;
         }
 
+        if self.stall > 0 {
+            self.stall -= 1;
+            return 0;
+        }
+
         // TIMERS
         self.update_timer_registers(bus);
 
+        // VRAM DMA halts the CPU while copying
+        if bus.hdma.stall > 0 {
+            bus.hdma.stall -= 1;
+            return 0;
+        }
+
         // INTERRUPTS
         if self.ime_delay {
-            //self.print_registers();
+            //self.print_registers(bus);
             self.ime_delay = false;
             self.ime = true;
         }
@@ -64,12 +85,10 @@ impl Cpu {
             return 0;
         }
 
-        // DMA
-        if bus.io_registers.dma_triggered {
-            println!("DMA triggered");
-            self.execute_dma(bus);
-            bus.io_registers.dma_triggered = false;
-            return steps - 40;
+        // TRACE
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.log(self, bus);
+            self.tracer = Some(tracer);
         }
 
         // OPCODE
@@ -112,13 +131,6 @@ impl Cpu {
         }
     }
 
-    fn execute_dma(&self, bus: &mut Bus) {
-        let starting_point = (bus.io_registers.get_byte(DMA) as u16) << 8;
-        for i in 0..0x100 {
-            bus.set_byte(0xFE00 + i, bus.get_byte(starting_point + i));
-        }
-    }
-
     #[inline]
     fn unset_interrupt_flag(&self, bus: &mut Bus, flag: &InterruptType) {
         bus.set_byte(IF, bus.get_byte(IF) & !(1 << *flag as usize));
@@ -148,15 +160,23 @@ impl Cpu {
         Opcode::fetch(opcode_id)
     }
 
-    pub fn print_registers(&self) {
-        println!("Registers:\tAF: 0x{:04X?}\tBC: 0x{:04X?}\tDE: 0x{:04X?}\tHL: 0x{:04X?}",
-                 self.registers.get_r16(RR::AF), self.registers.get_r16(RR::BC),
-                 self.registers.get_r16(RR::DE), self.registers.get_r16(RR::HL));
-        println!("\t\t\tSP: 0x{:04X?}\tPC: 0x{:04X?}", self.registers.get_r16(RR::SP),
-                 self.registers.get_r16(RR::PC));
-        println!("Flags:\t\tZ: {:?} N: {:?} C: {:?} H: {:?}", self.registers.is_flag(F::Z),
-                 self.registers.is_flag(F::N), self.registers.is_flag(F::C),
-                 self.registers.is_flag(F::H));
+    pub fn print_registers(&self, bus: &Bus) {
+        println!("{}", self.trace_registers(bus));
+    }
+
+    // Gameboy Doctor line for the current state, PCMEM being the 4 bytes at PC as
+    // stored, whatever the PPU or OAM DMA let the CPU see
+    pub fn trace_registers(&self, bus: &Bus) -> String {
+        let af: u16 = self.registers.get_r16(RR::AF);
+        let bc: u16 = self.registers.get_r16(RR::BC);
+        let de: u16 = self.registers.get_r16(RR::DE);
+        let hl: u16 = self.registers.get_r16(RR::HL);
+        let pc: u16 = self.registers.get_r16(RR::PC);
+        format!("A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+                af >> 8, af & 0xFF, bc >> 8, bc & 0xFF, de >> 8, de & 0xFF, hl >> 8, hl & 0xFF,
+                self.registers.get_r16(RR::SP), pc,
+                bus.peek(pc), bus.peek(pc.wrapping_add(1)),
+                bus.peek(pc.wrapping_add(2)), bus.peek(pc.wrapping_add(3)))
     }
 }
 
@@ -181,4 +201,30 @@ impl InterruptType {
             _ => panic!("invalid interrupt type")
         }
     }
-}
\ No newline at end of file
+}
+
+// the tracer is left out, it belongs to the session rather than the machine
+impl SaveState for Cpu {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.registers.save_state(w);
+        w.bool(self.ime);
+        w.bool(self.ime_delay);
+        w.bool(self.waiting_for_interrupt);
+        w.u16(self.tima_ctr);
+        w.bool(self.tma_flag);
+        w.bool(self.halted);
+        w.u16(self.stall);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.registers.load_state(r)?;
+        self.ime = r.bool()?;
+        self.ime_delay = r.bool()?;
+        self.waiting_for_interrupt = r.bool()?;
+        self.tima_ctr = r.u16()?;
+        self.tma_flag = r.bool()?;
+        self.halted = r.bool()?;
+        self.stall = r.u16()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt;
+
+use crate::cpu::registers::{R, RR};
+use crate::mmu::memory::Memory;
+
+// Condition codes used by JP, JR, CALL and RET
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Condition {
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Operand {
+    // a, b, c...
+    Reg8(R),
+    // bc, de, hl, sp, af
+    Reg16(RR),
+    // n8
+    Imm8(u8),
+    // n16
+    Imm16(u16),
+    // e8, used by ADD SP, e8
+    Signed(i8),
+    // [bc], [de], [hl]
+    Indirect(RR),
+    // [hl+]
+    HlInc,
+    // [hl-]
+    HlDec,
+    // [n16]
+    Absolute(u16),
+    // [$FF00 + n8], already resolved
+    High(u16),
+    // [c] as used by LDH
+    HighC,
+    // sp + e8, used by LD HL, SP + e8
+    SpOffset(i8),
+    // resolved JP/JR/CALL destination
+    Target(u16),
+    Condition(Condition),
+    // bit index of BIT/RES/SET
+    Bit(u8),
+    // RST vector
+    Vector(u8),
+}
+
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+}
+
+// Read-only view of a single ROM bank, mapped the way the cartridge would:
+// bank 0 at 0x0000 - 0x3FFF and any other bank at 0x4000 - 0x7FFF. Viewing bank 0
+// leaves bank 1 switched in above it, so code running past 0x3FFF decodes from there.
+pub struct RomBank<'a> {
+    rom: &'a [u8],
+    bank: usize,
+}
+
+impl<'a> RomBank<'a> {
+    pub fn new(rom: &'a [u8], bank: usize) -> Self {
+        RomBank { rom, bank }
+    }
+}
+
+impl<'a> Memory for RomBank<'a> {
+    fn get_byte(&self, address: u16) -> u8 {
+        let offset: usize = (address & 0x3FFF) as usize;
+        let bank: usize = if address < 0x4000 { 0 } else { self.bank.max(1) };
+        *self.rom.get(bank * 0x4000 + offset).unwrap_or(&0xFF)
+    }
+
+    fn set_byte(&mut self, _: u16, _: u8) {}
+}
+
+impl Memory for [u8] {
+    fn get_byte(&self, address: u16) -> u8 {
+        *self.get(address as usize).unwrap_or(&0xFF)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        if let Some(b) = self.get_mut(address as usize) {
+            *b = value;
+        }
+    }
+}
+
+// Register order of the r8 operand field in the opcode table
+static R8: [Option<R>; 8] = [Some(R::B), Some(R::C), Some(R::D), Some(R::E), Some(R::H), Some(R::L), None, Some(R::A)];
+static RP: [RR; 4] = [RR::BC, RR::DE, RR::HL, RR::SP];
+static RP2: [RR; 4] = [RR::BC, RR::DE, RR::HL, RR::AF];
+static CC: [Condition; 4] = [Condition::NZ, Condition::Z, Condition::NC, Condition::C];
+static ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+static ROT: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+impl Instruction {
+    pub fn decode<M: Memory + ?Sized>(mem: &M, address: u16) -> Instruction {
+        let mut decoder = Decoder { mem, pc: address, bytes: Vec::with_capacity(3) };
+        let (mnemonic, operands) = decoder.decode();
+        Instruction { address, bytes: decoder.bytes, mnemonic, operands }
+    }
+
+    // decodes `count` consecutive instructions starting at `address`
+    pub fn disassemble<M: Memory + ?Sized>(mem: &M, address: u16, count: usize) -> Vec<Instruction> {
+        let mut ret = Vec::with_capacity(count);
+        let mut pc: u16 = address;
+        for _ in 0..count {
+            let instruction = Instruction::decode(mem, pc);
+            pc = instruction.next_address();
+            ret.push(instruction);
+        }
+        ret
+    }
+
+    pub fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.size())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for (i, operand) in self.operands.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Reg8(r) => write!(f, "{}", r8_name(r)),
+            Operand::Reg16(rr) => write!(f, "{}", r16_name(rr)),
+            Operand::Imm8(n) => write!(f, "${:02X}", n),
+            Operand::Imm16(n) => write!(f, "${:04X}", n),
+            Operand::Signed(e) => write!(f, "{}", e),
+            Operand::Indirect(rr) => write!(f, "[{}]", r16_name(rr)),
+            Operand::HlInc => write!(f, "[hl+]"),
+            Operand::HlDec => write!(f, "[hl-]"),
+            Operand::Absolute(n) => write!(f, "[${:04X}]", n),
+            Operand::High(n) => write!(f, "[${:04X}]", n),
+            Operand::HighC => write!(f, "[c]"),
+            Operand::SpOffset(e) if e < 0 => write!(f, "sp - {}", -(e as i16)),
+            Operand::SpOffset(e) => write!(f, "sp + {}", e),
+            Operand::Target(n) => write!(f, "${:04X}", n),
+            Operand::Condition(c) => write!(f, "{}", match c {
+                Condition::NZ => "nz",
+                Condition::Z => "z",
+                Condition::NC => "nc",
+                Condition::C => "c",
+            }),
+            Operand::Bit(b) => write!(f, "{}", b),
+            Operand::Vector(v) => write!(f, "${:02X}", v),
+        }
+    }
+}
+
+fn r8_name(r: R) -> &'static str {
+    match r {
+        R::A => "a",
+        R::B => "b",
+        R::C => "c",
+        R::D => "d",
+        R::E => "e",
+        R::F => "f",
+        R::H => "h",
+        R::L => "l",
+    }
+}
+
+fn r16_name(rr: RR) -> &'static str {
+    match rr {
+        RR::AF => "af",
+        RR::BC => "bc",
+        RR::DE => "de",
+        RR::HL => "hl",
+        RR::SP => "sp",
+        RR::PC => "pc",
+    }
+}
+
+fn r8(i: u8) -> Operand {
+    match R8[i as usize] {
+        Some(r) => Operand::Reg8(r),
+        None => Operand::Indirect(RR::HL),
+    }
+}
+
+struct Decoder<'a, M: Memory + ?Sized> {
+    mem: &'a M,
+    pc: u16,
+    bytes: Vec<u8>,
+}
+
+impl<'a, M: Memory + ?Sized> Decoder<'a, M> {
+    fn next(&mut self) -> u8 {
+        let b: u8 = self.mem.get_byte(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        self.bytes.push(b);
+        b
+    }
+
+    fn next16(&mut self) -> u16 {
+        let lsb: u8 = self.next();
+        let msb: u8 = self.next();
+        (msb as u16) << 8 | lsb as u16
+    }
+
+    fn relative(&mut self) -> Operand {
+        let e: i8 = self.next() as i8;
+        Operand::Target(self.pc.wrapping_add(e as u16))
+    }
+
+    // opcodes are split as xx yyy zzz, with yyy further split as pp q
+    fn decode(&mut self) -> (&'static str, Vec<Operand>) {
+        use Operand::*;
+        let opcode: u8 = self.next();
+        let x: u8 = opcode >> 6;
+        let y: u8 = opcode >> 3 & 0b111;
+        let z: u8 = opcode & 0b111;
+        let p: usize = (y >> 1) as usize;
+        let q: bool = y & 1 == 1;
+
+        match (x, z) {
+            (0, 0) => match y {
+                0 => ("nop", vec![]),
+                1 => ("ld", vec![Absolute(self.next16()), Reg16(RR::SP)]),
+                2 => {
+                    self.next();
+                    ("stop", vec![])
+                }
+                3 => ("jr", vec![self.relative()]),
+                _ => ("jr", vec![Condition(CC[y as usize - 4]), self.relative()]),
+            },
+            (0, 1) if q => ("add", vec![Reg16(RR::HL), Reg16(RP[p])]),
+            (0, 1) => ("ld", vec![Reg16(RP[p]), Imm16(self.next16())]),
+            (0, 2) => {
+                let mem: Operand = match p {
+                    0 => Indirect(RR::BC),
+                    1 => Indirect(RR::DE),
+                    2 => HlInc,
+                    _ => HlDec,
+                };
+                if q {
+                    ("ld", vec![Reg8(R::A), mem])
+                } else {
+                    ("ld", vec![mem, Reg8(R::A)])
+                }
+            }
+            (0, 3) => (if q { "dec" } else { "inc" }, vec![Reg16(RP[p])]),
+            (0, 4) => ("inc", vec![r8(y)]),
+            (0, 5) => ("dec", vec![r8(y)]),
+            (0, 6) => ("ld", vec![r8(y), Imm8(self.next())]),
+            (0, _) => (["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"][y as usize], vec![]),
+            (1, 6) if y == 6 => ("halt", vec![]),
+            (1, _) => ("ld", vec![r8(y), r8(z)]),
+            (2, _) => (ALU[y as usize], vec![Reg8(R::A), r8(z)]),
+            (_, 0) => match y {
+                0..=3 => ("ret", vec![Condition(CC[y as usize])]),
+                4 => ("ldh", vec![High(0xFF00 | self.next() as u16), Reg8(R::A)]),
+                5 => ("add", vec![Reg16(RR::SP), Signed(self.next() as i8)]),
+                6 => ("ldh", vec![Reg8(R::A), High(0xFF00 | self.next() as u16)]),
+                _ => ("ld", vec![Reg16(RR::HL), SpOffset(self.next() as i8)]),
+            },
+            (_, 1) if !q => ("pop", vec![Reg16(RP2[p])]),
+            (_, 1) => match p {
+                0 => ("ret", vec![]),
+                1 => ("reti", vec![]),
+                2 => ("jp", vec![Reg16(RR::HL)]),
+                _ => ("ld", vec![Reg16(RR::SP), Reg16(RR::HL)]),
+            },
+            (_, 2) => match y {
+                0..=3 => ("jp", vec![Condition(CC[y as usize]), Target(self.next16())]),
+                4 => ("ldh", vec![HighC, Reg8(R::A)]),
+                5 => ("ld", vec![Absolute(self.next16()), Reg8(R::A)]),
+                6 => ("ldh", vec![Reg8(R::A), HighC]),
+                _ => ("ld", vec![Reg8(R::A), Absolute(self.next16())]),
+            },
+            (_, 3) => match y {
+                0 => ("jp", vec![Target(self.next16())]),
+                1 => self.decode_cb(),
+                6 => ("di", vec![]),
+                7 => ("ei", vec![]),
+                _ => ("db", vec![Imm8(opcode)]),
+            },
+            (_, 4) if y < 4 => ("call", vec![Condition(CC[y as usize]), Target(self.next16())]),
+            (_, 5) if !q => ("push", vec![Reg16(RP2[p])]),
+            (_, 5) if p == 0 => ("call", vec![Target(self.next16())]),
+            (_, 6) => (ALU[y as usize], vec![Reg8(R::A), Imm8(self.next())]),
+            (_, 7) => ("rst", vec![Vector(y * 8)]),
+            _ => ("db", vec![Imm8(opcode)]),
+        }
+    }
+
+    fn decode_cb(&mut self) -> (&'static str, Vec<Operand>) {
+        let opcode: u8 = self.next();
+        let y: u8 = opcode >> 3 & 0b111;
+        let z: u8 = opcode & 0b111;
+        match opcode >> 6 {
+            0 => (ROT[y as usize], vec![r8(z)]),
+            1 => ("bit", vec![Operand::Bit(y), r8(z)]),
+            2 => ("res", vec![Operand::Bit(y), r8(z)]),
+            _ => ("set", vec![Operand::Bit(y), r8(z)]),
+        }
+    }
+}
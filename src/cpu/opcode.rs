@@ -4,6 +4,9 @@ use crate::cpu::registers::*;
 use crate::mmu::bus::Bus;
 use std::borrow::BorrowMut;
 
+// M-cycles the CPU stays stopped while switching speed
+static SPEED_SWITCH_CYCLES: u16 = 2050;
+
 #[allow(non_camel_case_types, dead_code)]
 #[derive(Debug, PartialEq)]
 pub enum Opcode {
@@ -1040,6 +1043,14 @@ impl Opcode {
             // miscellaneous
             NOP => { 1 }
             STOP => {
+                // on CGB a STOP with KEY1 armed switches speed instead
+                if cpu.model.is_cgb() && bus.speed_switch_armed {
+                    bus.speed_switch_armed = false;
+                    bus.double_speed = !bus.double_speed;
+                    bus.io_registers.div = 0;
+                    cpu.stall = SPEED_SWITCH_CYCLES;
+                    return 1;
+                }
                 cpu.halted = true;
                 println!("halted requested");
                 0
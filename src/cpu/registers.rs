@@ -1,4 +1,7 @@
-#[derive(Debug, Copy, Clone)]
+use crate::model::Model;
+use crate::state::{SaveState, StateReader, StateWriter};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
 // 8 bits registers
 pub enum R {
     A,
@@ -11,7 +14,7 @@ pub enum R {
     L,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 // 16 bits registers
 pub enum RR {
     AF,
@@ -182,6 +185,31 @@ impl Registers {
     }
 }
 
+impl Registers {
+    // register values right after the boot ROM hands over to the cartridge. The
+    // DMG and MGB boot ROMs end by adding up the header, so H and C are only set
+    // when the header checksum at 0x14D is not 0.
+    pub fn post_boot(model: Model, header_checksum: u8) -> Self {
+        let f: u16 = if header_checksum == 0 { 0x80 } else { 0xB0 };
+        let (af, bc, de, hl): (u16, u16, u16, u16) = match model {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x0100 | f, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF00 | f, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+            Model::Agb => (0x1100, 0x0100, 0xFF56, 0x000D),
+        };
+        let mut registers: Registers = Default::default();
+        registers.set_r16(RR::AF, af);
+        registers.set_r16(RR::BC, bc);
+        registers.set_r16(RR::DE, de);
+        registers.set_r16(RR::HL, hl);
+        registers.set_r16(RR::SP, 0xFFFE);
+        registers.set_r16(RR::PC, 0x0100);
+        registers
+    }
+}
+
 impl Default for Registers {
     fn default() -> Self {
         Self {
@@ -197,4 +225,20 @@ impl Default for Registers {
             pc: Register { r: 0 },
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for Registers {
+    fn save_state(&self, w: &mut StateWriter) {
+        for rr in [RR::AF, RR::BC, RR::DE, RR::HL, RR::SP, RR::PC] {
+            w.u16(self.get_r16(rr));
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        for rr in [RR::AF, RR::BC, RR::DE, RR::HL, RR::SP, RR::PC] {
+            let value: u16 = r.u16()?;
+            self.set_r16(rr, value);
+        }
+        Ok(())
+    }
+}
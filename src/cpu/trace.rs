@@ -0,0 +1,21 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::cpu::cpu::Cpu;
+use crate::mmu::bus::Bus;
+
+// Writes one line per executed instruction in the Gameboy Doctor format:
+// A:00 F:11 B:22 C:33 D:44 E:55 H:66 L:77 SP:8888 PC:9999 PCMEM:AA,BB,CC,DD
+pub struct Tracer {
+    out: BufWriter<File>,
+}
+
+impl Tracer {
+    pub fn new(path: &str) -> std::io::Result<Self> {
+        Ok(Tracer { out: BufWriter::new(File::create(path)?) })
+    }
+
+    pub fn log(&mut self, cpu: &Cpu, bus: &Bus) {
+        writeln!(self.out, "{}", cpu.trace_registers(bus)).expect("unable to write trace");
+    }
+}
@@ -0,0 +1,12 @@
+// CRC-32 as used by PNG and zlib (reflected 0xEDB88320 polynomial), which also
+// tells ROMs apart in save states
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
@@ -2,24 +2,40 @@ use std::borrow::BorrowMut;
 
 use crate::cartridge::cartridge::CartridgeType;
 use crate::cpu::cpu::Cpu;
+use crate::cpu::trace::Tracer;
+use crate::crc;
+use crate::mmu::bios::Bios;
 use crate::mmu::bus::Bus;
+use crate::mmu::compat;
+use crate::model::Model;
+use crate::options::Options;
+use crate::png;
 use crate::ppu::ppu::Ppu;
-use crate::cpu::registers::RR;
+use crate::sgb::{self, Sgb};
+use crate::state::{self, SaveState, StateReader, StateWriter};
 
 pub struct Gameboy {
     pub bus: Bus,
     pub cpu: Cpu,
     pub ppu: Ppu,
+    // Super Game Boy colours and border, SGB model only
+    pub sgb: Option<Sgb>,
 
     pub clock_cpu: i32,
     pub clock_ppu: i32,
+    // dots since power on, which run at the same rate in both CPU speeds
+    pub time: u64,
+
+    pub model: Model,
+    // CRC-32 of the ROM, save states only load on the same one
+    pub rom_checksum: u32,
 
     debug: bool,
 }
 
 impl Gameboy {
     pub fn print(&self) {
-        self.cpu.print_registers();
+        self.cpu.print_registers(&self.bus);
         println!("LY: {:?} SCY: {:?} SCX: {:?}", self.bus.get_byte(0xFF44),
                  self.bus.get_byte(0xFF42), self.bus.get_byte(0xFF43));
         println!("LCDC: 0x{:X?} STAT: 0x{:X?} IE: {:X?} IF: {:X?} IME: {:?}", self.bus.get_byte(0xFF40),
@@ -29,22 +45,158 @@ impl Gameboy {
 
     pub fn tick(&mut self) {
         self.clock_cpu = self.cpu.tick(self.bus.borrow_mut(), self.clock_cpu + 1);
-        self.clock_ppu = self.ppu.tick(self.bus.borrow_mut(), self.clock_ppu + 1);
+        self.bus.tick_dma();
+        // the PPU runs 4 dots per M-cycle, 2 when the CPU runs at double speed
+        let dots: i32 = if self.bus.double_speed { 2 } else { 4 };
+        self.clock_ppu = self.ppu.tick(self.bus.borrow_mut(), self.clock_ppu + dots);
+        self.time += dots as u64;
+        if let Some(sgb) = &mut self.sgb {
+            sgb.tick(&mut self.bus.io_registers.p1, self.ppu.frames, &self.ppu.image);
+        }
+    }
+
+    // writes the last frame as a PNG file, coloured with the active palette or
+    // with the SGB colours and border
+    pub fn screenshot(&self, path: &str) -> std::io::Result<()> {
+        match &self.sgb {
+            Some(sgb) => png::write(path, sgb::WIDTH as u32, sgb::HEIGHT as u32, &sgb.frame(&self.ppu.image)),
+            None => png::write(path, 160, 144, &self.ppu.rgb_frame()),
+        }
     }
 
     pub fn load_rom(args: Vec<String>) -> Gameboy {
-        let rom_data: Vec<u8> = std::fs::read(args.get(1)
-            .unwrap_or(&String::from("roms/Battletoads (Japan).gb")))
-            .expect("file not found");
+        Gameboy::new(&Options::from_args(&args))
+    }
+
+    pub fn new(options: &Options) -> Gameboy {
+        let rom_data: Vec<u8> = std::fs::read(&options.rom).expect("file not found");
+        Gameboy::from_rom_bytes(&rom_data, options)
+    }
+
+    // same as new with the ROM already in memory, options.rom is not read
+    pub fn from_rom_bytes(rom: &[u8], options: &Options) -> Gameboy {
+        let rom_data: Vec<u8> = rom.to_vec();
+        let rom_checksum: u32 = crc::crc32(&rom_data);
 
         let cartridge_type: CartridgeType = CartridgeType::get_cartridge_type(rom_data.get(0x147).unwrap());
 
+        let model: Model = options.model.unwrap_or_else(|| Model::from_header(&rom_data));
+
         let cartridge = cartridge_type.load(rom_data);
 
-        let bus: Bus = Bus::new(cartridge);
-        let cpu: Cpu = Cpu::new();
-        let ppu: Ppu = Ppu::new();
+        let bus: Bus = Bus::new(cartridge, model);
+        let cpu: Cpu = Cpu::new(model);
+        let mut ppu: Ppu = Ppu::new(model);
+        ppu.palette = options.palette;
+        ppu.color_correction = options.color_correction;
+
+        let sgb: Option<Sgb> = if model == Model::Sgb { Some(Sgb::new()) } else { None };
+
+        let mut gb = Gameboy { bus, cpu, ppu, sgb, clock_cpu: 0, clock_ppu: 0, time: 0, model, rom_checksum, debug: false };
+        if let Some(path) = &options.bootrom {
+            let data: Vec<u8> = std::fs::read(path).expect("boot ROM not found");
+            gb.load_boot_rom(data).unwrap_or_else(|e| panic!("{}", e));
+        }
+        if options.skip_boot || (options.bootrom.is_none() && gb.model != Model::Dmg) {
+            if let Some(name) = &options.compat_palette {
+                compat::press(&mut gb.bus.io_registers.p1, name);
+            }
+            gb.skip_boot();
+        }
+        if let Some(path) = &options.trace {
+            gb.cpu.tracer = Some(Tracer::new(path).expect("unable to create trace file"));
+            gb.bus.doctor_ly = true;
+        }
+        gb
+    }
+
+    // snapshot of the whole machine, see state.rs for the format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w: StateWriter = StateWriter::new();
+        w.data.extend_from_slice(state::MAGIC);
+        w.u16(state::VERSION);
+        w.u32(self.rom_checksum);
+        w.u8(self.model as u8);
+
+        self.cpu.save_state(&mut w);
+        self.bus.save_state(&mut w);
+        self.ppu.save_state(&mut w);
+        w.u32(self.clock_cpu as u32);
+        w.u32(self.clock_ppu as u32);
+        w.u64(self.time);
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(&mut w);
+        }
+        w.data
+    }
+
+    // restores a snapshot taken by save_state with the same ROM and model. The
+    // machine is left as it was when the data doesn't match or is cut short.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut r: StateReader = StateReader::new(data);
+        self.check_state_header(&mut r)?;
+        let backup: Vec<u8> = self.save_state();
+        let result: Result<(), String> = self.load_components(&mut r);
+        if result.is_err() {
+            let mut r: StateReader = StateReader::new(&backup);
+            self.check_state_header(&mut r)
+                .and_then(|_| self.load_components(&mut r))
+                .expect("restoring the machine after a failed load");
+        }
+        result
+    }
+
+    fn check_state_header(&self, r: &mut StateReader) -> Result<(), String> {
+        let mut magic: [u8; 4] = [0; 4];
+        for b in magic.iter_mut() {
+            *b = r.u8()?;
+        }
+        if &magic != state::MAGIC {
+            return Err(String::from("not a save state"));
+        }
+        let version: u16 = r.u16()?;
+        if version != state::VERSION {
+            return Err(format!("save state version {} is not supported, expected {}", version, state::VERSION));
+        }
+        let checksum: u32 = r.u32()?;
+        if checksum != self.rom_checksum {
+            return Err(format!("save state is for another ROM (CRC-32 {:08X}, loaded {:08X})", checksum, self.rom_checksum));
+        }
+        let model: u8 = r.u8()?;
+        if model != self.model as u8 {
+            return Err(format!("save state is for another model, running as {:?}", self.model));
+        }
+        Ok(())
+    }
+
+    // everything after the header, possibly stopping halfway on bad data
+    fn load_components(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.cpu.load_state(r)?;
+        self.bus.load_state(r)?;
+        self.ppu.load_state(r)?;
+        self.clock_cpu = r.u32()? as i32;
+        self.clock_ppu = r.u32()? as i32;
+        self.time = r.u64()?;
+        if let Some(sgb) = &mut self.sgb {
+            sgb.load_state(r)?;
+        }
+        if !r.finished() {
+            return Err(String::from("save state has trailing data"));
+        }
+        Ok(())
+    }
+
+    // replaces the embedded DMG boot ROM with one matching the selected model
+    pub fn load_boot_rom(&mut self, data: Vec<u8>) -> Result<(), String> {
+        self.bus.bios = Bios::load(self.model, data)?;
+        Ok(())
+    }
 
-        Gameboy { bus, cpu, ppu, clock_cpu: 0, clock_ppu: 0, debug: false }
+    // unmaps the boot ROM and starts at 0x0100 with the post-boot registers,
+    // I/O and DIV state of the selected model
+    pub fn skip_boot(&mut self) {
+        self.cpu.skip_boot(self.bus.cartridge.get_byte(0x14D));
+        self.bus.skip_boot();
+        self.ppu.skip_boot();
     }
 }
\ No newline at end of file
@@ -0,0 +1,176 @@
+use std::path::Path;
+
+use crate::gameboy::Gameboy;
+use crate::model::Model;
+use crate::options::Options;
+use crate::png;
+use crate::ppu::palette::Palette;
+
+static IF: u16 = 0xFF0F;
+
+// Golden-frame regression cases: a ROM runs headless for a number of frames with
+// scripted input, then its last frame is compared with a reference PNG drawn with
+// the greyscale palette.
+//
+// Manifest lines, paths being relative to the manifest:
+//   name rom frames reference [model=NAME] [FRAME:+BUTTON] [FRAME:-BUTTON]
+// where +BUTTON presses and -BUTTON releases a button once FRAME frames are done.
+// Empty lines and lines starting with # are ignored.
+pub struct GoldenCase {
+    pub name: String,
+    pub rom: String,
+    pub frames: u64,
+    pub reference: String,
+    pub model: Option<Model>,
+    pub inputs: Vec<Input>,
+}
+
+pub struct Input {
+    pub frame: u64,
+    pub button: String,
+    pub pressed: bool,
+}
+
+pub enum Outcome {
+    Passed,
+    // the ROM is not available locally, nothing was run
+    Skipped,
+    // the reference did not exist and was written from this run
+    Blessed,
+    // the actual frame and an image highlighting the differing pixels in red were written
+    Failed { mismatches: usize, actual: String, diff: String },
+}
+
+impl GoldenCase {
+    pub fn load_manifest(path: &str) -> Result<Vec<GoldenCase>, String> {
+        let text: String = std::fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+        let base: &Path = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+        text.lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+            .map(|(i, l)| GoldenCase::parse(l, base).map_err(|e| format!("{}:{}: {}", path, i + 1, e)))
+            .collect()
+    }
+
+    pub fn parse(line: &str, base: &Path) -> Result<GoldenCase, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(String::from("expected name, rom, frames and reference"));
+        }
+        let relative = |p: &str| base.join(p).to_string_lossy().into_owned();
+        let mut case = GoldenCase {
+            name: fields[0].to_string(),
+            rom: relative(fields[1]),
+            frames: fields[2].parse().map_err(|_| format!("invalid frame count {}", fields[2]))?,
+            reference: relative(fields[3]),
+            model: None,
+            inputs: Vec::new(),
+        };
+        for field in &fields[4..] {
+            if let Some(name) = field.strip_prefix("model=") {
+                case.model = Some(Model::from_name(name));
+                continue;
+            }
+            let (frame, action) = field.split_once(':').ok_or_else(|| format!("invalid input {}", field))?;
+            let frame: u64 = frame.parse().map_err(|_| format!("invalid input frame {}", field))?;
+            let (pressed, button) = if let Some(b) = action.strip_prefix('+') {
+                (true, b)
+            } else if let Some(b) = action.strip_prefix('-') {
+                (false, b)
+            } else {
+                return Err(format!("input {} neither presses (+) nor releases (-)", field));
+            };
+            case.inputs.push(Input { frame, button: button.to_string(), pressed });
+        }
+        Ok(case)
+    }
+
+    // runs the case and compares the last frame. With `bless` a missing reference
+    // is written from this run instead of failing, results go to `out_dir`.
+    pub fn run(&self, out_dir: &str, bless: bool) -> Result<Outcome, String> {
+        if !Path::new(&self.rom).exists() {
+            return Ok(Outcome::Skipped);
+        }
+        let actual: Vec<u8> = self.play()?;
+
+        if !Path::new(&self.reference).exists() {
+            if !bless {
+                return Err(format!("missing reference {}, run with GOLDEN_BLESS=1 to create it", self.reference));
+            }
+            png::write(&self.reference, 160, 144, &actual).map_err(|e| e.to_string())?;
+            return Ok(Outcome::Blessed);
+        }
+
+        let (width, height, expected) = png::read(&self.reference)?;
+        if (width, height) != (160, 144) {
+            return Err(format!("{} is {}x{}, expected 160x144", self.reference, width, height));
+        }
+        let mismatches: usize = expected.chunks(3).zip(actual.chunks(3)).filter(|(e, a)| e != a).count();
+        if mismatches == 0 {
+            return Ok(Outcome::Passed);
+        }
+
+        std::fs::create_dir_all(out_dir).map_err(|e| e.to_string())?;
+        let actual_path: String = format!("{}/{}.actual.png", out_dir, self.name);
+        let diff_path: String = format!("{}/{}.diff.png", out_dir, self.name);
+        png::write(&actual_path, 160, 144, &actual).map_err(|e| e.to_string())?;
+        png::write(&diff_path, 160, 144, &diff(&expected, &actual)).map_err(|e| e.to_string())?;
+        Ok(Outcome::Failed { mismatches, actual: actual_path, diff: diff_path })
+    }
+
+    // RGB pixels of the last frame
+    pub fn play(&self) -> Result<Vec<u8>, String> {
+        let options = Options {
+            rom: self.rom.clone(),
+            skip_boot: true,
+            model: self.model,
+            palette: Palette::greyscale(),
+            ..Default::default()
+        };
+        let mut gb = Gameboy::new(&options);
+        while gb.ppu.frames < self.frames {
+            let frame: u64 = gb.ppu.frames;
+            for input in self.inputs.iter().filter(|i| i.frame == frame) {
+                set_button(&mut gb, &input.button, input.pressed)?;
+            }
+            while gb.ppu.frames == frame {
+                gb.tick();
+            }
+        }
+        Ok(gb.ppu.rgb_frame())
+    }
+}
+
+fn set_button(gb: &mut Gameboy, button: &str, pressed: bool) -> Result<(), String> {
+    let p1 = &mut gb.bus.io_registers.p1;
+    let state: &mut bool = match button.to_lowercase().as_str() {
+        "a" => &mut p1.a,
+        "b" => &mut p1.b,
+        "select" => &mut p1.select,
+        "start" => &mut p1.start,
+        "up" => &mut p1.up,
+        "down" => &mut p1.down,
+        "left" => &mut p1.left,
+        "right" => &mut p1.right,
+        _ => return Err(format!("unknown button {}", button))
+    };
+    *state = pressed;
+    if pressed {
+        gb.bus.set_byte(IF, gb.bus.get_byte(IF) | 0b10000);
+    }
+    Ok(())
+}
+
+// matching pixels faded out, differing ones in red
+fn diff(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    let mut image: Vec<u8> = Vec::with_capacity(expected.len());
+    for (e, a) in expected.chunks(3).zip(actual.chunks(3)) {
+        if e == a {
+            image.extend(e.iter().map(|c| c / 4 + 192));
+        } else {
+            image.extend_from_slice(&[255, 0, 0]);
+        }
+    }
+    image
+}
@@ -0,0 +1,34 @@
+use crate::gameboy::Gameboy;
+
+// Two CGBs facing each other: the LED of each one lights the receiver of the other.
+// The one behind in time always runs next, keeping them within an M-cycle of each
+// other whatever their CPU speed, so pulses keep their length on the other side.
+pub struct IrLink {
+    pub a: Gameboy,
+    pub b: Gameboy,
+}
+
+impl IrLink {
+    pub fn new(a: Gameboy, b: Gameboy) -> Self {
+        IrLink { a, b }
+    }
+
+    // runs one M-cycle of the Game Boy behind
+    pub fn tick(&mut self) {
+        if self.a.time <= self.b.time {
+            self.a.tick();
+        } else {
+            self.b.tick();
+        }
+        self.a.bus.infrared.receiving = self.b.bus.infrared.led();
+        self.b.bus.infrared.receiving = self.a.bus.infrared.led();
+    }
+
+    // runs both Game Boys for `dots` dots
+    pub fn run(&mut self, dots: u64) {
+        let end: u64 = self.a.time.max(self.b.time) + dots;
+        while self.a.time < end || self.b.time < end {
+            self.tick();
+        }
+    }
+}
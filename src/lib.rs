@@ -1,6 +1,8 @@
 pub mod cpu {
     pub mod cpu;
     pub mod registers;
+    pub mod disassembler;
+    pub mod trace;
     mod opcode;
 }
 
@@ -13,13 +15,17 @@ pub mod cartridge {
 pub mod ppu {
     pub mod ppu;
     pub mod oam_entry;
+    pub mod fetcher;
+    pub mod palette;
+    pub mod debug;
 }
 
 pub mod mmu {
     pub mod bus;
     pub mod memory;
     pub mod joypad;
-    mod bios;
+    pub mod bios;
+    pub mod compat;
     mod interrupt;
     mod vram;
     mod ext_ram;
@@ -27,6 +33,18 @@ pub mod mmu {
     mod io_registers;
     mod oam;
     mod hram;
+    mod dma;
+    mod palette_ram;
+    mod hdma;
+    mod infrared;
 }
 
-pub mod gameboy;
\ No newline at end of file
+pub mod crc;
+pub mod gameboy;
+pub mod golden;
+pub mod ir_link;
+pub mod model;
+pub mod options;
+pub mod png;
+pub mod sgb;
+pub mod state;
\ No newline at end of file
@@ -1,58 +1,154 @@
 use pixel_engine::traits::ScreenTrait;
 
+use gamerust::cpu::disassembler::{Instruction, RomBank};
 use gamerust::gameboy::Gameboy;
+use gamerust::options::Options;
+use gamerust::ppu::debug;
+use gamerust::sgb;
 use pixel_engine::vector2::Vu2d;
 use pixel_engine::inputs::Keycodes;
-use pixel_engine::Color;
+
+static QUICK_SAVE: &str = "quicksave.state";
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
-    let mut gb: Gameboy = Gameboy::load_rom(args);
+    if args.get(1).map(String::as_str) == Some("disasm") {
+        disasm(&args[2..]);
+        return;
+    }
+    let options: Options = Options::from_args(&args);
+    let mut gb: Gameboy = Gameboy::new(&options);
+    let mut screenshot_at: Option<u64> = options.screenshot_at;
+    println!("Running as {:?}", gb.model);
 
-    let game = pixel_engine::EngineWrapper::new("gamerust".to_owned(), (800, 300, 4));
+    let game = pixel_engine::EngineWrapper::new("gamerust".to_owned(), (810, 300, 4));
 
     game.run(move |game: &mut pixel_engine::Engine| {
         game.clear([0, 64, 255].into());
 
         handle_input(game, &mut gb);
 
-        while !gb.ppu.ready {
-            gb.tick();
+        if game.get_key(Keycodes::F12).pressed {
+            screenshot(&gb);
         }
 
-        if gb.bus.get_byte(0xFF40) >> 7 & 1 == 1 {
-            for (i, c) in gb.ppu.get_image().iter().enumerate() {
-                game.draw(Vu2d::from(((i % 160 + 5) as u32, (i / 160 + 5) as u32)), get_color(*c));
+        // quick save and load
+        if game.get_key(Keycodes::F5).pressed {
+            match std::fs::write(QUICK_SAVE, gb.save_state()) {
+                Ok(()) => println!("State saved to {}", QUICK_SAVE),
+                Err(e) => println!("Unable to write {}: {}", QUICK_SAVE, e),
             }
-        } else {
-            for (i, _c) in gb.ppu.get_image().iter().enumerate() {
-                game.draw(Vu2d::from(((i % 160 + 5) as u32, (i / 160 + 5) as u32)), [0, 0, 0].into());
+        }
+        if game.get_key(Keycodes::F9).pressed {
+            match std::fs::read(QUICK_SAVE).map_err(|e| e.to_string()).and_then(|data| gb.load_state(&data)) {
+                Ok(()) => println!("State loaded from {}", QUICK_SAVE),
+                Err(e) => println!("Unable to load {}: {}", QUICK_SAVE, e),
             }
         }
 
-        for (i, c) in gb.ppu.bg.iter().enumerate() {
-            game.draw(Vu2d::from(((i % 256 + 180) as u32, (i / 256 + 5) as u32)), get_color(*c));
+        while !gb.ppu.ready {
+            gb.tick();
+        }
+        // --screenshot-at-frame N, once frame N is complete
+        if screenshot_at.map_or(false, |frame| gb.ppu.frames >= frame) {
+            screenshot_at = None;
+            screenshot(&gb);
         }
 
-        for (i, c) in gb.ppu.bg2.iter().enumerate() {
-            game.draw(Vu2d::from(((i % 256 + 450) as u32, (i / 256 + 5) as u32)), get_color(*c));
+        // the SGB draws its border around the screen
+        let (frame, width): (Vec<u8>, usize) = match &gb.sgb {
+            Some(sgb) => (sgb.frame(&gb.ppu.image), sgb::WIDTH),
+            None => (gb.ppu.rgb_frame(), 160),
+        };
+        gb.ppu.ready = false;
+        for (i, c) in frame.chunks(3).enumerate() {
+            game.draw(Vu2d::from(((i % width + 5) as u32, (i / width + 5) as u32)), [c[0], c[1], c[2]].into());
         }
 
+        for (map, left) in [(0, width + 20), (1, width + 290)].iter() {
+            let view = debug::tile_map(&gb.bus, &gb.ppu.palette, gb.ppu.color_correction, *map);
+            for (i, c) in view.rgb.chunks(3).enumerate() {
+                game.draw(Vu2d::from(((i % view.width + left) as u32, (i / view.width + 5) as u32)), [c[0], c[1], c[2]].into());
+            }
+        }
 
         Ok(true)
     });
 }
 
-fn get_color(i: u8) -> Color {
-    match i {
-        0 => [155, 188, 15].into(),
-        1 => [132, 172, 15].into(),
-        2 => [48, 98, 48].into(),
-        3 => [15, 56, 15].into(),
-        _ => panic!("as")
+// writes the last frame to screenshot-N.png, N being the frame count
+fn screenshot(gb: &Gameboy) {
+    let path: String = format!("screenshot-{}.png", gb.ppu.frames);
+    match gb.screenshot(&path) {
+        Ok(()) => println!("Screenshot saved to {}", path),
+        Err(e) => println!("Unable to write {}: {}", path, e),
     }
 }
 
+// gamerust disasm rom.gb [--bank N] [--from ADDR] [--count N]
+fn disasm(args: &[String]) {
+    let path = args.get(0).expect("usage: gamerust disasm rom.gb [--bank N] [--from ADDR] [--count N]");
+    let rom: Vec<u8> = std::fs::read(path).expect("file not found");
+
+    let mut bank: Option<usize> = None;
+    let mut from: Option<u16> = None;
+    let mut count: Option<usize> = None;
+    let mut it = args[1..].iter();
+    while let Some(arg) = it.next() {
+        let value = it.next().unwrap_or_else(|| panic!("missing value for {}", arg));
+        match arg.as_str() {
+            "--bank" => bank = Some(parse_number(value) as usize),
+            "--from" => {
+                let address: u32 = parse_number(value);
+                if address > 0xFFFF {
+                    panic!("address {} is outside the 16-bit address space", value);
+                }
+                from = Some(address as u16);
+            }
+            "--count" => count = Some(parse_number(value) as usize),
+            _ => panic!("unknown option {}", arg)
+        }
+    }
+
+    let from: u16 = from.unwrap_or(if bank.unwrap_or(0) == 0 { 0x0100 } else { 0x4000 });
+    let bank: usize = bank.unwrap_or(if from < 0x4000 { 0 } else { 1 });
+    if from >= 0x8000 || (bank == 0) != (from < 0x4000) {
+        panic!("address 0x{:04X?} is not mapped by bank {}", from, bank);
+    }
+    if bank * 0x4000 >= rom.len() {
+        panic!("bank {} is out of range, the ROM only has {} banks", bank, rom.len() / 0x4000);
+    }
+
+    let mem = RomBank::new(&rom, bank);
+    let end: u32 = if bank == 0 { 0x4000 } else { 0x8000 };
+
+    if bank == 0 {
+        println!("SECTION \"ROM0 ${:04X}\", ROM0[${:04X}]", from, from);
+    } else {
+        println!("SECTION \"ROMX ${:03X}:${:04X}\", ROMX[${:04X}], BANK[${:X}]", bank, from, from, bank);
+    }
+
+    let mut pc: u32 = from as u32;
+    let mut lines: usize = 0;
+    while pc < end && count.map_or(true, |c| lines < c) {
+        let instruction = Instruction::decode(&mem, pc as u16);
+        let bytes: Vec<String> = instruction.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        println!("    {:<24} ; ${:04X}: {}", instruction.to_string(), instruction.address, bytes.join(" "));
+        pc += instruction.size() as u32;
+        lines += 1;
+    }
+}
+
+// accepts decimal, 0x-prefixed or $-prefixed hexadecimal values
+fn parse_number(s: &str) -> u32 {
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        u32::from_str_radix(hex, 16)
+    } else {
+        s.parse::<u32>()
+    };
+    parsed.unwrap_or_else(|_| panic!("invalid number {}", s))
+}
+
 #[inline]
 fn handle_input(game: &mut pixel_engine::Engine, gb: &mut Gameboy) {
     if game.get_key(Keycodes::A).released {
@@ -1,26 +1,49 @@
 use crate::mmu::memory::Memory;
+use crate::model::Model;
 
+// DMG0, DMG, MGB and SGB boot ROMs are 256 bytes mapped at 0x0000 - 0x00FF.
+// CGB and AGB ones are 2304 bytes, mapped at 0x0000 - 0x00FF and 0x0200 - 0x08FF
+// leaving the cartridge header at 0x0100 - 0x01FF visible.
 pub struct Bios {
-    mem: [u8; 0x100]
+    mem: Vec<u8>
+}
+
+impl Bios {
+    pub fn load(model: Model, data: Vec<u8>) -> Result<Bios, String> {
+        let size: usize = Bios::size(model);
+        if data.len() != size {
+            return Err(format!("{:?} boot ROM must be {} bytes, got {}", model, size, data.len()));
+        }
+        Ok(Bios { mem: data })
+    }
+
+    pub fn size(model: Model) -> usize {
+        if model.is_cgb() { 0x900 } else { 0x100 }
+    }
+
+    // whether the boot ROM overlays the cartridge at this address while mapped
+    pub fn maps(&self, address: u16) -> bool {
+        address < 0x100 || (address >= 0x200 && (address as usize) < self.mem.len())
+    }
 }
 
 impl Memory for Bios {
     fn get_byte(&self, address: u16) -> u8 {
-        if address > 0xFF {
+        if !self.maps(address) {
             panic!("Unaccessible memory.");
         };
         self.mem[address as usize]
     }
 
     fn set_byte(&mut self, _: u16, _: u8) {
-        unimplemented!()
+        // read only
     }
 }
 
 impl Default for Bios {
     fn default() -> Bios {
         Bios {
-            mem: [
+            mem: vec![
                 0x31, 0xFE, 0xFF, 0xAF, 0x21, 0xFF, 0x9F, 0x32, 0xCB, 0x7C, 0x20, 0xFB, 0x21, 0x26, 0xFF, 0x0E,
                 0x11, 0x3E, 0x80, 0x32, 0xE2, 0x0C, 0x3E, 0xF3, 0xE2, 0x32, 0x3E, 0x77, 0x77, 0x3E, 0xFC, 0xE0,
                 0x47, 0x11, 0x04, 0x01, 0x21, 0x10, 0x80, 0x1A, 0xCD, 0x95, 0x00, 0xCD, 0x96, 0x00, 0x13, 0x7B,
@@ -2,17 +2,34 @@ use std::borrow::BorrowMut;
 
 use crate::cartridge::cartridge::Cartridge;
 use crate::mmu::bios::Bios;
+use crate::mmu::compat;
+use crate::mmu::dma::Dma;
 use crate::mmu::ext_ram::ExtRam;
+use crate::mmu::hdma::Hdma;
 use crate::mmu::hram::HRam;
+use crate::mmu::infrared::Infrared;
 use crate::mmu::interrupt::Interrupt;
 use crate::mmu::io_registers::IORegisters;
 use crate::mmu::memory::Memory;
 use crate::mmu::oam::OAM;
+use crate::mmu::palette_ram::PaletteRam;
 use crate::mmu::vram::VRam;
 use crate::mmu::work_ram::WorkRam;
+use crate::model::Model;
+use crate::state::{SaveState, StateReader, StateWriter};
+
+static DMA: u16 = 0xFF46;
+static LY: u16 = 0xFF44;
+static KEY0: u16 = 0xFF4C;
+static KEY1: u16 = 0xFF4D;
+static VBK: u16 = 0xFF4F;
+static RP: u16 = 0xFF56;
+static SVBK: u16 = 0xFF70;
 
 pub struct Bus {
+    pub model: Model,
     // 0x0000 - 0x00FF - 256B BIOS
+    // 0x0200 - 0x08FF - 1792B more on CGB
     pub bios: Bios,
     // 0x0000 - 0x7FFF - 32kb ROM
     pub cartridge: Box<dyn Cartridge>,
@@ -30,30 +47,84 @@ pub struct Bus {
 
     // 0xFF00 - 0xFF7F - 128B I/O registers
     pub io_registers: IORegisters,
+    // 0xFF68 - 0xFF6B - CGB palettes
+    pub palette_ram: PaletteRam,
     // 0xFF80 - 0xFFFE - 127B HRAM
     pub hram: HRam,
     //          0xFFFF - Interrupts
     pub interrupts: Interrupt,
+    pub dma: Dma,
+    // CGB VRAM DMA
+    pub hdma: Hdma,
+    // CGB infrared port
+    pub infrared: Infrared,
+    // CGB double speed mode, the CPU runs two M-cycles per 4 dots instead of one
+    pub double_speed: bool,
+    // KEY1 bit 0: the next STOP switches speed
+    pub speed_switch_armed: bool,
+    // CGB running a DMG cartridge, set through KEY0 by the boot ROM: the CGB
+    // registers are gone and the PPU colours BGP/OBP0/OBP1 with palettes 0 and 1
+    pub dmg_compat: bool,
+    // LY always reads 0x90 like the emulator Gameboy Doctor logs come from, so
+    // traces of ROMs waiting for VBlank can be compared with them
+    pub doctor_ly: bool,
+    // blocks CPU access to VRAM and OAM while the PPU uses them, debugging tools may turn it off
+    pub access_checks: bool,
 }
 
 impl Bus {
-    pub fn new(rom: Box<dyn Cartridge>) -> Self {
-        Bus {
+    pub fn new(rom: Box<dyn Cartridge>, model: Model) -> Self {
+        let mut bus = Bus {
+            model,
             bios: Default::default(),
             cartridge: rom,
-            vram: Default::default(),
+            vram: VRam::new(model.is_cgb()),
             ext_ram: Default::default(),
-            work_ram: Default::default(),
+            work_ram: WorkRam::new(model.is_cgb()),
             oam: Default::default(),
             io_registers: Default::default(),
+            palette_ram: PaletteRam::new(model.is_cgb()),
             hram: Default::default(),
             interrupts: Default::default(),
-        }
+            dma: Default::default(),
+            hdma: Hdma::new(model.is_cgb()),
+            infrared: Infrared::new(model.is_cgb()),
+            double_speed: false,
+            speed_switch_armed: false,
+            dmg_compat: false,
+            doctor_ly: false,
+            access_checks: true,
+        };
+        bus.io_registers.p1.sgb = model == Model::Sgb;
+        bus
     }
 
+    // what the CPU reads, 0xFF while OAM DMA or the PPU keep it off the memory
     pub fn get_byte(&self, address: u16) -> u8 {
+        if self.dma_conflict(address) {
+            return 0xFF;
+        }
+        if (0x8000..0xA000).contains(&address) && !self.vram_accessible() {
+            return 0xFF;
+        }
+        if (0xFE00..0xFEA0).contains(&address) && !self.oam_accessible() {
+            return 0xFF;
+        }
+        // palette data is locked during mode 3 like VRAM
+        if (address == 0xFF69 || address == 0xFF6B) && !self.vram_accessible() {
+            return 0xFF;
+        }
+        if address == LY && self.doctor_ly {
+            return 0x90;
+        }
+        self.peek(address)
+    }
+
+    // debugger read: what is mapped at `address`, ignoring the VRAM and OAM locks
+    // and OAM DMA bus conflicts
+    pub fn peek(&self, address: u16) -> u8 {
         if address < 0x8000 {
-            if self.boot_rom() && address < 0x100 {
+            if self.boot_rom() && self.bios.maps(address) {
                 return self.bios.get_byte(address);
             }
             return (*self.cartridge).get_byte(address);
@@ -68,7 +139,27 @@ impl Bus {
         } else if address < 0xFEA0 && address >= 0xFE00 {
             self.oam.get_byte(address)
         } else if address < 0xFEFF && address >= 0xFEA0 {
-            return 0xFF;
+            0xFF
+        } else if self.cgb_register(address) {
+            if !self.cgb_registers() {
+                return 0xFF;
+            }
+            if address == VBK {
+                self.vram.get_vbk()
+            } else if address == SVBK {
+                self.work_ram.get_svbk()
+            } else if (0xFF51..=0xFF55).contains(&address) {
+                self.hdma.get_byte(address)
+            } else if address == RP {
+                self.infrared.get_byte()
+            } else {
+                self.palette_ram.get_byte(address)
+            }
+        } else if address == KEY1 {
+            if !self.cgb_registers() {
+                return 0xFF;
+            }
+            0x7E | (self.double_speed as u8) << 7 | self.speed_switch_armed as u8
         } else if address < 0xFF80 && address >= 0xFF00 {
             self.io_registers.get_byte(address)
         } else if address < 0xFFFF && address >= 0xFF80 {
@@ -79,13 +170,15 @@ impl Bus {
     }
 
     pub fn set_byte(&mut self, address: u16, value: u8) {
+        if self.dma_conflict(address) {
+            return;
+        }
         if address < 0x8000 {
-            if self.boot_rom() && address < 0x100 {
-                self.bios.set_byte(address, value)
-            }
             (*self.cartridge).borrow_mut().set_byte(address, value)
         } else if address < 0xA000 && address >= 0x8000 {
-            self.vram.set_byte(address, value)
+            if self.vram_accessible() {
+                self.vram.set_byte(address, value)
+            }
         } else if address < 0xC000 && address >= 0xA000 {
             self.ext_ram.set_byte(address, value)
         } else if address < 0xE000 && address >= 0xC000 {
@@ -93,11 +186,49 @@ impl Bus {
         } else if address < 0xFDFF && address >= 0xE000 {
             self.work_ram.set_byte(address - 0x2000, value)
         } else if address < 0xFEA0 && address >= 0xFE00 {
-            self.oam.set_byte(address, value)
+            if self.oam_accessible() {
+                self.oam.set_byte(address, value)
+            }
         } else if address < 0xFEFF && address >= 0xFEA0 {
             // do nothing
+        } else if self.cgb_register(address) {
+            if !self.cgb_registers() {
+                return;
+            }
+            if address == VBK {
+                self.vram.set_vbk(value)
+            } else if address == SVBK {
+                self.work_ram.set_svbk(value)
+            } else if (0xFF51..=0xFF55).contains(&address) {
+                self.hdma.set_byte(address, value);
+                while self.hdma.general_pending() {
+                    self.copy_hdma_block();
+                }
+                // a HBlank DMA started in HBlank or with the LCD off (STAT mode 0
+                // either way) copies its first block right away
+                if address == 0xFF55 && self.hdma.hblank_pending() && self.ppu_mode() == 0 {
+                    self.copy_hdma_block();
+                }
+            } else if address == RP {
+                self.infrared.set_byte(value)
+            } else if (address == 0xFF69 || address == 0xFF6B) && !self.vram_accessible() {
+                self.palette_ram.skip_write(address);
+            } else {
+                self.palette_ram.set_byte(address, value);
+            }
+        } else if address == KEY0 {
+            // bit 2 only: the boot ROM writes 0x04 for DMG cartridges before unmapping
+            if self.model.is_cgb() && self.boot_rom() {
+                self.dmg_compat = value & 0x0C == 0x04;
+            }
+        } else if address == KEY1 {
+            // compatibility mode has no double speed
+            self.speed_switch_armed = self.cgb_registers() && value & 0x1 == 1;
         } else if address < 0xFF80 && address >= 0xFF00 {
-            self.io_registers.set_byte(address, value)
+            self.io_registers.set_byte(address, value);
+            if address == DMA {
+                self.dma.start(value);
+            }
         } else if address < 0xFFFF && address >= 0xFF80 {
             self.hram.set_byte(address, value)
         } else {
@@ -105,8 +236,146 @@ impl Bus {
         }
     }
 
+    // unmaps the boot ROM leaving the I/O registers as it would have
+    pub fn skip_boot(&mut self) {
+        // a CGB boot ROM colourizes DMG cartridges, with the combo held at power on if any
+        if self.model.is_cgb() && self.cartridge.get_byte(0x143) & 0x80 == 0 {
+            let palettes = compat::held(&self.io_registers.p1).unwrap_or_else(|| compat::lookup(&*self.cartridge));
+            compat::load(&mut self.palette_ram, &palettes);
+            self.dmg_compat = true;
+        }
+        self.io_registers = IORegisters::post_boot(self.model);
+        self.io_registers.p1.sgb = self.model == Model::Sgb;
+        self.interrupts = Default::default();
+    }
+
+    // copies the next OAM DMA byte, called once per M-cycle
+    pub fn tick_dma(&mut self) {
+        if let Some((source, offset)) = self.dma.step() {
+            let value: u8 = self.dma_source_byte(source);
+            self.oam.set_byte(0xFE00 + offset, value);
+        }
+    }
+
+    // the PPU entering HBlank on a visible line lets a HBlank DMA copy one block
+    pub fn hblank(&mut self) {
+        if self.hdma.hblank_pending() {
+            self.copy_hdma_block();
+        }
+    }
+
+    // copies 16 bytes into the selected VRAM bank, halting the CPU for 8 M-cycles
+    // (16 at double speed, so the copy takes the same time)
+    fn copy_hdma_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+        for i in 0..0x10 {
+            let value: u8 = self.dma_source_byte(source.wrapping_add(i));
+            self.vram.set_byte(destination + i, value);
+        }
+        self.hdma.stall += if self.double_speed { 16 } else { 8 };
+    }
+
+    // DMA reads straight from the memory behind the source, anything above 0xDFFF
+    // ends up in echo RAM
+    fn dma_source_byte(&self, address: u16) -> u8 {
+        if address < 0x8000 {
+            (*self.cartridge).get_byte(address)
+        } else if address < 0xA000 {
+            self.vram.get_byte(address)
+        } else if address < 0xC000 {
+            self.ext_ram.get_byte(address)
+        } else if address < 0xE000 {
+            self.work_ram.get_byte(address)
+        } else {
+            self.work_ram.get_byte(address - 0x2000)
+        }
+    }
+
+    // VBK, HDMA1-5, RP, the palette registers and SVBK
+    fn cgb_register(&self, address: u16) -> bool {
+        address == VBK || address == SVBK || address == RP || (0xFF51..=0xFF55).contains(&address) || (0xFF68..=0xFF6B).contains(&address)
+    }
+
+    // the CGB registers answer on CGB hardware, in compatibility mode only while
+    // the boot ROM sets up the palettes
+    fn cgb_registers(&self) -> bool {
+        self.model.is_cgb() && (!self.dmg_compat || self.boot_rom())
+    }
+
+    // while OAM DMA runs the CPU only reaches HRAM and the I/O registers,
+    // everything below 0xFF00 reads 0xFF and ignores writes
+    fn dma_conflict(&self, address: u16) -> bool {
+        self.access_checks && self.dma.active() && address < 0xFF00
+    }
+
+    // VRAM is locked while the PPU draws a line (mode 3)
+    pub fn vram_accessible(&self) -> bool {
+        !self.access_checks || self.ppu_mode() != 3
+    }
+
+    // OAM is locked during OAM search and drawing (modes 2 and 3)
+    pub fn oam_accessible(&self) -> bool {
+        !self.access_checks || self.ppu_mode() < 2
+    }
+
+    // mode in STAT, which the PPU keeps up to date and is 0 while the LCD is off
+    fn ppu_mode(&self) -> u8 {
+        self.io_registers.stat & 0b11
+    }
+
     pub fn boot_rom(&self) -> bool {
         self.io_registers.boot
     }
 }
 
+// Memory view for debugging tools such as the disassembler: reads go through peek,
+// so VRAM, OAM and everything under OAM DMA show their contents
+impl Memory for Bus {
+    fn get_byte(&self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn set_byte(&mut self, address: u16, value: u8) {
+        Bus::set_byte(self, address, value)
+    }
+}
+
+// the boot ROM is not saved, whether it is still mapped is part of the I/O registers
+impl SaveState for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.cartridge.save_state(w);
+        self.vram.save_state(w);
+        self.ext_ram.save_state(w);
+        self.work_ram.save_state(w);
+        self.oam.save_state(w);
+        self.io_registers.save_state(w);
+        self.palette_ram.save_state(w);
+        self.hram.save_state(w);
+        self.interrupts.save_state(w);
+        self.dma.save_state(w);
+        self.hdma.save_state(w);
+        self.infrared.save_state(w);
+        w.bool(self.double_speed);
+        w.bool(self.speed_switch_armed);
+        w.bool(self.dmg_compat);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.cartridge.load_state(r)?;
+        self.vram.load_state(r)?;
+        self.ext_ram.load_state(r)?;
+        self.work_ram.load_state(r)?;
+        self.oam.load_state(r)?;
+        self.io_registers.load_state(r)?;
+        self.palette_ram.load_state(r)?;
+        self.hram.load_state(r)?;
+        self.interrupts.load_state(r)?;
+        self.dma.load_state(r)?;
+        self.hdma.load_state(r)?;
+        self.infrared.load_state(r)?;
+        self.double_speed = r.bool()?;
+        self.speed_switch_armed = r.bool()?;
+        self.dmg_compat = r.bool()?;
+        Ok(())
+    }
+}
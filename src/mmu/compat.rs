@@ -0,0 +1,312 @@
+use crate::cartridge::cartridge::Cartridge;
+use crate::mmu::joypad::Joypad;
+use crate::mmu::palette_ram::PaletteRam;
+
+// Colours the CGB boot ROM gives cartridges without CGB support. Games licensed
+// by Nintendo are looked up by the checksum of their title, the others and the
+// unknown ones get the default set. Holding a direction, optionally with A or B,
+// while the logo is shown picks one of 12 sets instead. The tables below are the
+// boot ROM's own.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct CompatPalettes {
+    // RGB555 colours for the shades 0-3 of BGP, OBP0 and OBP1
+    pub bg: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+}
+
+// 30 palettes of 4 colours. Some combinations start a palette one colour early,
+// so they are kept in one run.
+static COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, // 0
+    0x639F, 0x4279, 0x15B0, 0x04CB,
+    0x7FFF, 0x6E31, 0x454A, 0x0000,
+    0x7FFF, 0x1BEF, 0x0200, 0x0000,
+    0x7FFF, 0x421F, 0x1CF2, 0x0000,
+    0x7FFF, 0x5294, 0x294A, 0x0000, // 5
+    0x7FFF, 0x03FF, 0x012F, 0x0000,
+    0x7FFF, 0x03EF, 0x01D6, 0x0000,
+    0x7FFF, 0x42B5, 0x3DC8, 0x0000,
+    0x7E74, 0x03FF, 0x0180, 0x0000,
+    0x67FF, 0x77AC, 0x1A13, 0x2D6B, // 10
+    0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000,
+    0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0,
+    0x03ED, 0x7FFF, 0x255F, 0x0000,
+    0x036A, 0x021F, 0x03FF, 0x7FFF, // 15
+    0x7FFF, 0x01DF, 0x0112, 0x0000,
+    0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000,
+    0x299F, 0x001A, 0x000C, 0x0000,
+    0x7FFF, 0x027F, 0x001F, 0x0000, // 20
+    0x7FFF, 0x03E0, 0x0206, 0x0120,
+    0x7FFF, 0x7EEB, 0x001F, 0x7C00,
+    0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000,
+    0x03FF, 0x001F, 0x000C, 0x0000, // 25
+    0x7FFF, 0x033F, 0x0193, 0x0000,
+    0x0000, 0x4200, 0x037F, 0x7FFF,
+    0x7FFF, 0x7E8C, 0x7C00, 0x0000,
+    0x7FFF, 0x1BEF, 0x6180, 0x0000,
+];
+
+// OBJ0, OBJ1 and BG of every combination, as offsets into COLORS
+static COMBINATIONS: [(usize, usize, usize); 51] = [
+    (4 * 4, 4 * 4, 29 * 4), // 0, default
+    (18 * 4, 18 * 4, 18 * 4),
+    (20 * 4, 20 * 4, 20 * 4),
+    (24 * 4, 24 * 4, 24 * 4),
+    (9 * 4, 9 * 4, 9 * 4),
+    (0, 0, 0), // 5
+    (27 * 4, 27 * 4, 27 * 4),
+    (5 * 4, 5 * 4, 5 * 4),
+    (12 * 4, 12 * 4, 12 * 4),
+    (26 * 4, 26 * 4, 26 * 4),
+    (16 * 4, 8 * 4, 8 * 4), // 10
+    (4 * 4, 28 * 4, 28 * 4),
+    (4 * 4, 2 * 4, 2 * 4),
+    (3 * 4, 4 * 4, 4 * 4),
+    (4 * 4, 29 * 4, 29 * 4),
+    (28 * 4, 4 * 4, 28 * 4), // 15
+    (2 * 4, 17 * 4, 2 * 4),
+    (16 * 4, 16 * 4, 8 * 4),
+    (4 * 4, 4 * 4, 7 * 4),
+    (4 * 4, 4 * 4, 18 * 4),
+    (4 * 4, 4 * 4, 20 * 4), // 20
+    (19 * 4, 19 * 4, 9 * 4),
+    (4 * 4 - 1, 4 * 4 - 1, 11 * 4),
+    (17 * 4, 17 * 4, 2 * 4),
+    (4 * 4, 4 * 4, 2 * 4),
+    (4 * 4, 4 * 4, 3 * 4), // 25
+    (28 * 4, 28 * 4, 0),
+    (3 * 4, 3 * 4, 0),
+    (0, 0, 4),
+    (18 * 4, 22 * 4, 18 * 4),
+    (20 * 4, 22 * 4, 20 * 4), // 30
+    (24 * 4, 22 * 4, 24 * 4),
+    (16 * 4, 22 * 4, 8 * 4),
+    (17 * 4, 4 * 4, 13 * 4),
+    (28 * 4 - 1, 0, 14 * 4),
+    (28 * 4 - 1, 4 * 4, 15 * 4), // 35
+    (19 * 4, 22 * 4, 9 * 4),
+    (16 * 4, 28 * 4, 10 * 4),
+    (4 * 4, 23 * 4, 28 * 4),
+    (17 * 4, 22 * 4, 2 * 4),
+    (4 * 4, 0, 2 * 4), // 40
+    (4 * 4, 28 * 4, 3 * 4),
+    (28 * 4, 3 * 4, 0),
+    (3 * 4, 28 * 4, 4 * 4),
+    (21 * 4, 28 * 4, 4 * 4),
+    (3 * 4, 28 * 4, 0), // 45
+    (25 * 4, 3 * 4, 28 * 4),
+    (0, 28 * 4, 8 * 4),
+    (4 * 4, 3 * 4, 28 * 4),
+    (28 * 4, 3 * 4, 6 * 4),
+    (4 * 4, 28 * 4, 29 * 4), // 50
+];
+
+static DEFAULT: usize = 0;
+
+// button combos as "direction" or "direction+a" / "direction+b" and their combination
+static COMBOS: [(&str, usize); 12] = [
+    ("up", 5),
+    ("up+a", 43),
+    ("up+b", 28),
+    ("left", 48),
+    ("left+a", 40),
+    ("left+b", 7),
+    ("down", 8),
+    ("down+a", 3),
+    ("down+b", 49),
+    ("right", 1),
+    ("right+a", 0),
+    ("right+b", 6),
+];
+
+// title checksum and combination of the titles the checksum alone identifies
+static TITLES: [(u8, usize); 65] = [
+    (0x00, 0),
+    (0x88, 4),  // ALLEY WAY
+    (0x16, 5),  // YAKUMAN
+    (0x36, 35), // BASEBALL, GAME AND WATCH 2
+    (0xD1, 34), // TENNIS
+    (0xDB, 3),  // TETRIS
+    (0xF2, 31), // QIX
+    (0x3C, 15), // DR.MARIO
+    (0x8C, 10), // RADARMISSION
+    (0x92, 5),  // F1RACE
+    (0x3D, 19), // YOSSY NO TAMAGO
+    (0x5C, 36),
+    (0x58, 7),  // X
+    (0xC9, 37), // MARIOLAND2
+    (0x3E, 30), // YOSSY NO COOKIE
+    (0x70, 44), // ZELDA
+    (0x1D, 21),
+    (0x59, 32),
+    (0x69, 31), // TETRIS FLASH
+    (0x19, 20), // DONKEY KONG
+    (0x35, 5),  // MARIO'S PICROSS
+    (0xA8, 33),
+    (0x14, 13), // POKEMON RED, GAMEBOYCAMERA G
+    (0xAA, 14), // POKEMON GREEN
+    (0x75, 5),  // PICROSS 2
+    (0x95, 29), // YOSSY NO PANEPON
+    (0x99, 5),  // KIRAKIRA KIDS
+    (0x34, 18), // GAMEBOY GALLERY
+    (0x6F, 9),  // POCKETCAMERA
+    (0x15, 3),
+    (0xFF, 2),  // BALLOON KID
+    (0x97, 26), // KINGOFTHEZOO
+    (0x4B, 25), // DMG FOOTBALL
+    (0x90, 25), // WORLD CUP
+    (0x17, 41), // OTHELLO
+    (0x10, 42), // SUPER RC PRO-AM
+    (0x39, 26), // DYNABLASTER
+    (0xF7, 45), // BOY AND BLOB GB2
+    (0xF6, 42), // MEGAMAN
+    (0xA2, 45), // STAR WARS-NOA
+    (0x49, 36),
+    (0x4E, 38), // WAVERACE
+    (0x43, 26),
+    (0x68, 42), // LOLO2
+    (0xE0, 30), // YOSHI'S COOKIE
+    (0x8B, 41), // MYSTIC QUEST
+    (0xF0, 34),
+    (0xCE, 34), // TOPRANKINGTENNIS
+    (0x0C, 5),  // MANSELL
+    (0x29, 42), // MEGAMAN3
+    (0xE8, 6),  // SPACE INVADERS
+    (0xB7, 5),  // GAME&WATCH
+    (0x86, 33), // DONKEYKONGLAND95
+    (0x9A, 25), // ASTEROIDS/MISCMD
+    (0x52, 42), // STREET FIGHTER 2
+    (0x01, 42), // DEFENDER/JOUST
+    (0x9D, 40), // KILLERINSTINCT95
+    (0x71, 14), // TETRIS BLAST
+    (0x9C, 16), // PINOCCHIO
+    (0xBD, 25),
+    (0x5D, 5),  // BA.TOSHINDEN
+    (0x6D, 29), // NETTOU KOF 95
+    (0x67, 5),
+    (0x3F, 29), // TETRIS PLUS
+    (0x6B, 39), // DONKEYKONGLAND 3
+];
+
+// titles sharing a checksum, told apart by their fourth letter. A checksum found
+// here without a matching letter gets the default set.
+static FOURTH_LETTER_TITLES: [(u8, u8, usize); 29] = [
+    (0xB3, b'B', 36),
+    (0x46, b'E', 22), // SUPER MARIOLAND
+    (0x28, b'F', 25), // GOLF
+    (0xA5, b'A', 6),  // SOLARSTRIKER
+    (0xC6, b'A', 32), // GBWARS
+    (0xD3, b'R', 12), // KAERUNOTAMENI
+    (0x27, b'B', 36),
+    (0x61, b'E', 11), // POKEMON BLUE
+    (0x18, b'K', 39), // DONKEYKONGLAND
+    (0x66, b'E', 18), // GAMEBOY GALLERY2
+    (0x6A, b'K', 39), // DONKEYKONGLAND 2
+    (0xBF, b' ', 24), // KID ICARUS
+    (0x0D, b'R', 31), // TETRIS2
+    (0xF4, b'-', 50),
+    (0xB3, b'U', 17), // MOGURANYA
+    (0x46, b'R', 46),
+    (0x28, b'A', 6),  // GALAGA&GALAXIAN
+    (0xA5, b'R', 27), // BT2RAGNAROKWORLD
+    (0xC6, b' ', 0),  // KEN GRIFFEY JR
+    (0xD3, b'I', 47),
+    (0x27, b'N', 41), // MAGNETIC SOCCER
+    (0x61, b'A', 41), // VEGAS STAKES
+    (0x18, b'I', 0),
+    (0x66, b'L', 0),  // MILLI/CENTI/PEDE
+    (0x6A, b'I', 19), // MARIO & YOSHI
+    (0xBF, b'C', 34), // SOCCER
+    (0x0D, b'E', 23), // POKEBOM
+    (0xF4, b' ', 18), // G&W GALLERY
+    (0xB3, b'R', 29), // TETRIS ATTACK
+];
+
+impl CompatPalettes {
+    // colours of entry `index` of COMBINATIONS
+    pub fn combination(index: usize) -> CompatPalettes {
+        let (obj0, obj1, bg) = COMBINATIONS[index];
+        let colors = |offset: usize| -> [u16; 4] { [COLORS[offset], COLORS[offset + 1], COLORS[offset + 2], COLORS[offset + 3]] };
+        CompatPalettes { bg: colors(bg), obj0: colors(obj0), obj1: colors(obj1) }
+    }
+}
+
+// colours picked by a combo name, see COMBOS
+pub fn combo(name: &str) -> Option<CompatPalettes> {
+    COMBOS.iter().find(|(n, _)| *n == name.to_lowercase()).map(|(_, c)| CompatPalettes::combination(*c))
+}
+
+// holds the buttons of a combo name, panicking on unknown ones
+pub fn press(p1: &mut Joypad, name: &str) {
+    if combo(name).is_none() {
+        panic!("unknown palette combo: {}", name);
+    }
+    for button in name.to_lowercase().split('+') {
+        match button {
+            "up" => p1.up = true,
+            "down" => p1.down = true,
+            "left" => p1.left = true,
+            "right" => p1.right = true,
+            "a" => p1.a = true,
+            "b" => p1.b = true,
+            _ => {}
+        }
+    }
+}
+
+// the combo held on the joypad, if any. Only one direction counts, A wins over B.
+pub fn held(p1: &Joypad) -> Option<CompatPalettes> {
+    let direction: &str = if p1.up {
+        "up"
+    } else if p1.left {
+        "left"
+    } else if p1.down {
+        "down"
+    } else if p1.right {
+        "right"
+    } else {
+        return None;
+    };
+    let name: String = if p1.a {
+        format!("{}+a", direction)
+    } else if p1.b {
+        format!("{}+b", direction)
+    } else {
+        direction.to_string()
+    };
+    combo(&name)
+}
+
+// colours the boot ROM picks from the cartridge header
+pub fn lookup(cartridge: &dyn Cartridge) -> CompatPalettes {
+    let old_licensee: u8 = cartridge.get_byte(0x14B);
+    let nintendo: bool = old_licensee == 0x01
+        || (old_licensee == 0x33 && cartridge.get_byte(0x144) == b'0' && cartridge.get_byte(0x145) == b'1');
+    if !nintendo {
+        return CompatPalettes::combination(DEFAULT);
+    }
+    let checksum: u8 = (0x134..0x144).fold(0_u8, |sum, a| sum.wrapping_add(cartridge.get_byte(a)));
+    let fourth: u8 = cartridge.get_byte(0x137);
+    let combination: usize = TITLES.iter()
+        .find(|(c, _)| *c == checksum)
+        .map(|(_, i)| *i)
+        .or_else(|| FOURTH_LETTER_TITLES.iter().find(|(c, l, _)| *c == checksum && *l == fourth).map(|(_, _, i)| *i))
+        .unwrap_or(DEFAULT);
+    CompatPalettes::combination(combination)
+}
+
+// writes the colours into BG palette 0 and OBJ palettes 0 and 1, which the PPU
+// uses for BGP, OBP0 and OBP1 in compatibility mode
+pub fn load(palette_ram: &mut PaletteRam, palettes: &CompatPalettes) {
+    for (index, address, colors) in [(0x80, 0xFF68, &palettes.bg), (0x80, 0xFF6A, &palettes.obj0), (0x88, 0xFF6A, &palettes.obj1)] {
+        palette_ram.set_byte(address, index);
+        for color in colors {
+            palette_ram.set_byte(address + 1, *color as u8);
+            palette_ram.set_byte(address + 1, (color >> 8) as u8);
+        }
+    }
+}
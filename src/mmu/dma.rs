@@ -0,0 +1,65 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+
+// OAM DMA started by writing the source page to 0xFF46. After one M-cycle of
+// setup it copies one byte per M-cycle, 160 in total, into OAM.
+#[derive(Default)]
+pub struct Dma {
+    // first address of the transfer in progress
+    source: u16,
+    // next OAM byte to copy, 0 - 159
+    index: u16,
+    active: bool,
+    // transfer requested on the last write to 0xFF46, it takes over after the setup cycle
+    pending: Option<u16>,
+}
+
+impl Dma {
+    // writing again while a transfer runs restarts it, the old one keeps going
+    // during the setup cycle of the new one
+    pub fn start(&mut self, page: u8) {
+        self.pending = Some((page as u16) << 8);
+    }
+
+    // whether OAM is in use by a transfer
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    // advances one M-cycle, returning the source address and OAM offset to copy if any
+    pub fn step(&mut self) -> Option<(u16, u16)> {
+        let mut copy: Option<(u16, u16)> = None;
+        if self.active {
+            copy = Some((self.source + self.index, self.index));
+            self.index += 1;
+            if self.index == 0xA0 {
+                self.active = false;
+            }
+        }
+        if let Some(source) = self.pending.take() {
+            self.source = source;
+            self.index = 0;
+            self.active = true;
+        }
+        copy
+    }
+}
+
+impl SaveState for Dma {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.source);
+        w.u16(self.index);
+        w.bool(self.active);
+        w.bool(self.pending.is_some());
+        w.u16(self.pending.unwrap_or(0));
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.source = r.u16()?;
+        self.index = r.u16()?;
+        self.active = r.bool()?;
+        let pending: bool = r.bool()?;
+        let source: u16 = r.u16()?;
+        self.pending = if pending { Some(source) } else { None };
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub struct ExtRam {
     bank0: [u8; 0x2000],
@@ -28,4 +29,14 @@ impl Memory for ExtRam {
             panic!("Unaccessible memory.")
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for ExtRam {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.bank0);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.bank0)
+    }
+}
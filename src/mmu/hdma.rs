@@ -0,0 +1,106 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+
+// CGB VRAM DMA through HDMA1-5 (0xFF51 - 0xFF55). Copies blocks of 16 bytes from
+// ROM or RAM into the VRAM bank currently selected, either all at once (general
+// purpose) or one block at the start of every HBlank.
+pub struct Hdma {
+    cgb: bool,
+    source: u16,
+    destination: u16,
+    // blocks left minus one, HDMA5 bits 0-6
+    remaining: u8,
+    active: bool,
+    // HBlank DMA rather than general purpose
+    hblank: bool,
+    // M-cycles the CPU is still halted for by a copy
+    pub stall: u16,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Hdma::new(false)
+    }
+}
+
+impl Hdma {
+    pub fn new(cgb: bool) -> Self {
+        Hdma { cgb, source: 0, destination: 0x8000, remaining: 0x7F, active: false, hblank: false, stall: 0 }
+    }
+
+    pub fn get_byte(&self, address: u16) -> u8 {
+        match address {
+            // HDMA5 tells whether a transfer runs (bit 7 clear) and the blocks left,
+            // 0xFF once done
+            0xFF55 if self.cgb => (!self.active as u8) << 7 | self.remaining,
+            _ => 0xFF
+        }
+    }
+
+    pub fn set_byte(&mut self, address: u16, value: u8) {
+        if !self.cgb {
+            return;
+        }
+        match address {
+            0xFF51 => self.source = (value as u16) << 8 | (self.source & 0x00FF),
+            0xFF52 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            0xFF53 => self.destination = 0x8000 | ((value & 0x1F) as u16) << 8 | (self.destination & 0x00FF),
+            0xFF54 => self.destination = (self.destination & 0xFF00) | (value & 0xF0) as u16,
+            0xFF55 => {
+                if self.active && self.hblank && value & 0x80 == 0 {
+                    // cancels the HBlank DMA, HDMA5 keeps the blocks left
+                    self.active = false;
+                } else {
+                    self.remaining = value & 0x7F;
+                    self.hblank = value & 0x80 != 0;
+                    self.active = true;
+                }
+            }
+            _ => panic!("Unaccessible memory.")
+        }
+    }
+
+    // a general purpose transfer waiting to be copied in one go
+    pub fn general_pending(&self) -> bool {
+        self.active && !self.hblank
+    }
+
+    pub fn hblank_pending(&self) -> bool {
+        self.active && self.hblank
+    }
+
+    // source and destination of the next block, moving on to the following one
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block: (u16, u16) = (self.source, self.destination);
+        self.source = self.source.wrapping_add(0x10);
+        // the destination wraps within VRAM
+        self.destination = 0x8000 | (self.destination + 0x10) & 0x1FF0;
+        if self.remaining == 0 {
+            self.active = false;
+            self.remaining = 0x7F;
+        } else {
+            self.remaining -= 1;
+        }
+        block
+    }
+}
+
+impl SaveState for Hdma {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.source);
+        w.u16(self.destination);
+        w.u8(self.remaining);
+        w.bool(self.active);
+        w.bool(self.hblank);
+        w.u16(self.stall);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.source = r.u16()?;
+        self.destination = r.u16()?;
+        self.remaining = r.u8()?;
+        self.active = r.bool()?;
+        self.hblank = r.bool()?;
+        self.stall = r.u16()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub struct HRam {
     mem: [u8; 127],
@@ -28,4 +29,14 @@ impl Memory for HRam {
             panic!("Unaccessible memory.")
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for HRam {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.mem);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.mem)
+    }
+}
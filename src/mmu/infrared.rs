@@ -0,0 +1,53 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+
+// CGB infrared port, RP (0xFF56): bit 0 lights the LED, bits 6-7 both set enable
+// reading and bit 1 then reads 0 while a signal is received.
+pub struct Infrared {
+    cgb: bool,
+    value: u8,
+    // light from the other side, see IrLink
+    pub receiving: bool,
+}
+
+impl Default for Infrared {
+    fn default() -> Self {
+        Infrared::new(false)
+    }
+}
+
+impl Infrared {
+    pub fn new(cgb: bool) -> Self {
+        Infrared { cgb, value: 0, receiving: false }
+    }
+
+    pub fn get_byte(&self) -> u8 {
+        if !self.cgb {
+            return 0xFF;
+        }
+        let signal: bool = self.value & 0xC0 == 0xC0 && self.receiving;
+        0x3C | self.value & 0xC1 | (!signal as u8) << 1
+    }
+
+    pub fn set_byte(&mut self, value: u8) {
+        if self.cgb {
+            self.value = value & 0xC1;
+        }
+    }
+
+    pub fn led(&self) -> bool {
+        self.value & 0x1 == 1
+    }
+}
+
+impl SaveState for Infrared {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.value);
+        w.bool(self.receiving);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.value = r.u8()?;
+        self.receiving = r.bool()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub struct Interrupt {
     value: u8,
@@ -35,4 +36,16 @@ impl Memory for Interrupt {
         self.serial = value & 0x8 == 1;
         self.joypad = value & 0x10 == 1;
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for Interrupt {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.value);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let value: u8 = r.u8()?;
+        self.set_byte(0xFFFF, value);
+        Ok(())
+    }
+}
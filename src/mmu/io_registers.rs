@@ -1,5 +1,7 @@
 use crate::mmu::memory::Memory;
 use crate::mmu::joypad::Joypad;
+use crate::model::Model;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub struct IORegisters {
     // interrupt request
@@ -46,7 +48,6 @@ pub struct IORegisters {
     pub ly: u8,
     pub lyc: u8,
     dma: u8,
-    pub dma_triggered: bool,
     bgp: u8,
     obp0: u8,
     obp1: u8,
@@ -96,7 +97,6 @@ impl Default for IORegisters {
             ly: 0,
             lyc: 0,
             dma: 0,
-            dma_triggered: false,
             bgp: 0,
             obp0: 0,
             obp1: 0,
@@ -108,6 +108,55 @@ impl Default for IORegisters {
     }
 }
 
+impl IORegisters {
+    // I/O state left behind by the boot ROM, as documented in the Pan Docs.
+    // The Pan Docs only give DIV for DMG0, DMG and MGB: on SGB it depends on how
+    // long the packet transfers to the SNES take and on CGB/AGB on the boot ROM
+    // path taken for the cartridge. The SGB and CGB values here are not measured,
+    // load the model's boot ROM with --bootrom when DIV at 0x0100 matters.
+    pub fn post_boot(model: Model) -> Self {
+        let mut io: IORegisters = Default::default();
+        io.p1.set_byte(0xFF00, 0xCF);
+        io.sc = if model.is_cgb() { 0x7F } else { 0x7E };
+        io.div = match model {
+            Model::Dmg0 => 0x1830,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            Model::Sgb => 0x0000,
+            Model::Cgb | Model::Agb => 0x267C,
+        };
+        io.tac = 0xF8;
+        io.interrupt_flag = 0xE1;
+        io.nr10 = 0x80;
+        io.nr11 = 0xBF;
+        io.nr12 = 0xF3;
+        io.nr13 = 0xFF;
+        io.nr14 = 0xBF;
+        io.nr21 = 0x3F;
+        io.nr22 = 0x00;
+        io.nr23 = 0xFF;
+        io.nr24 = 0xBF;
+        io.nr30 = 0x7F;
+        io.nr31 = 0xFF;
+        io.nr32 = 0x9F;
+        io.nr33 = 0xFF;
+        io.nr34 = 0xBF;
+        io.nr41 = 0xFF;
+        io.nr42 = 0x00;
+        io.nr43 = 0x00;
+        io.nr44 = 0xBF;
+        io.nr50 = 0x77;
+        io.nr51 = 0xF3;
+        io.nr52 = if model == Model::Sgb { 0xF0 } else { 0xF1 };
+        io.lcdc = 0x91;
+        io.stat = 0x85;
+        io.dma = if model.is_cgb() { 0x00 } else { 0xFF };
+        io.bgp = 0xFC;
+        io.bootrom = 0x01;
+        io.boot = false;
+        io
+    }
+}
+
 impl Memory for IORegisters {
     fn get_byte(&self, address: u16) -> u8 {
         match address {
@@ -239,19 +288,114 @@ impl Memory for IORegisters {
                 println!("trying to write LY");
             }
             0xFF45 => self.lyc = value,
-            0xFF46 => {
-                self.dma = value;
-                self.dma_triggered = true
-            }
+            0xFF46 => self.dma = value,
             0xFF47 => self.bgp = value,
             0xFF48 => self.obp0 = value,
             0xFF49 => self.obp1 = value,
             0xFF4A => self.wy = value,
             0xFF4B => self.wx = value,
             0xFF50 => {
-                if self.boot && value == 0x1 { self.boot = false; }
+                // any value with bit 0 set unmaps it, the CGB boot ROM writes 0x11
+                if self.boot && value & 0x1 != 0 { self.boot = false; }
             }
             _ => println!("trying to write in {:04X?}", address)
         };
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for IORegisters {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.p1.save_state(w);
+        w.u16(self.div);
+        w.u8(self.interrupt_flag);
+        w.u8(self.sb);
+        w.u8(self.sc);
+        w.u8(self.tima);
+        w.u8(self.tma);
+        w.u8(self.tac);
+        w.u8(self.nr10);
+        w.u8(self.nr11);
+        w.u8(self.nr12);
+        w.u8(self.nr13);
+        w.u8(self.nr14);
+        w.u8(self.nr21);
+        w.u8(self.nr22);
+        w.u8(self.nr23);
+        w.u8(self.nr24);
+        w.u8(self.nr30);
+        w.u8(self.nr31);
+        w.u8(self.nr32);
+        w.u8(self.nr33);
+        w.u8(self.nr34);
+        w.u8(self.nr41);
+        w.u8(self.nr42);
+        w.u8(self.nr43);
+        w.u8(self.nr44);
+        w.u8(self.nr50);
+        w.u8(self.nr51);
+        w.u8(self.nr52);
+        w.u8(self.lcdc);
+        w.u8(self.stat);
+        w.u8(self.scy);
+        w.u8(self.scx);
+        w.u8(self.ly);
+        w.u8(self.lyc);
+        w.u8(self.dma);
+        w.u8(self.bgp);
+        w.u8(self.obp0);
+        w.u8(self.obp1);
+        w.u8(self.wy);
+        w.u8(self.wx);
+        w.u8(self.bootrom);
+        w.bytes(&self.wave_ram);
+        w.bool(self.boot);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.p1.load_state(r)?;
+        self.div = r.u16()?;
+        self.interrupt_flag = r.u8()?;
+        self.sb = r.u8()?;
+        self.sc = r.u8()?;
+        self.tima = r.u8()?;
+        self.tma = r.u8()?;
+        self.tac = r.u8()?;
+        self.nr10 = r.u8()?;
+        self.nr11 = r.u8()?;
+        self.nr12 = r.u8()?;
+        self.nr13 = r.u8()?;
+        self.nr14 = r.u8()?;
+        self.nr21 = r.u8()?;
+        self.nr22 = r.u8()?;
+        self.nr23 = r.u8()?;
+        self.nr24 = r.u8()?;
+        self.nr30 = r.u8()?;
+        self.nr31 = r.u8()?;
+        self.nr32 = r.u8()?;
+        self.nr33 = r.u8()?;
+        self.nr34 = r.u8()?;
+        self.nr41 = r.u8()?;
+        self.nr42 = r.u8()?;
+        self.nr43 = r.u8()?;
+        self.nr44 = r.u8()?;
+        self.nr50 = r.u8()?;
+        self.nr51 = r.u8()?;
+        self.nr52 = r.u8()?;
+        self.lcdc = r.u8()?;
+        self.stat = r.u8()?;
+        self.scy = r.u8()?;
+        self.scx = r.u8()?;
+        self.ly = r.u8()?;
+        self.lyc = r.u8()?;
+        self.dma = r.u8()?;
+        self.bgp = r.u8()?;
+        self.obp0 = r.u8()?;
+        self.obp1 = r.u8()?;
+        self.wy = r.u8()?;
+        self.wx = r.u8()?;
+        self.bootrom = r.u8()?;
+        r.bytes_into(&mut self.wave_ram)?;
+        self.boot = r.bool()?;
+        Ok(())
+    }
+}
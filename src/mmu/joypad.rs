@@ -1,7 +1,20 @@
+use std::collections::VecDeque;
+
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub struct Joypad {
     value: u8,
+    // SGB: 16 byte command packets are sent bit by bit with P14/P15 pulses, a
+    // reset pulse (both low) starts a packet, P15 low sends a 1 and P14 low a 0,
+    // each pulse followed by both high. Bit 128 is a stop bit.
+    pub sgb: bool,
+    received: Option<usize>,
+    packet: [u8; 16],
+    pub packets: VecDeque<[u8; 16]>,
+    // SGB MLT_REQ: 1, 2 or 4 joypads, only the first one has buttons here
+    pub players: u8,
+    player: u8,
     pub a: bool,
     pub b: bool,
     pub select: bool,
@@ -15,7 +28,10 @@ pub struct Joypad {
 impl Memory for Joypad {
     fn get_byte(&self, _: u16) -> u8 {
         let mut res: u8 = 0xFF;
-        if (self.value >> 5) & 0x1 == 0 {
+        if self.player != 0 {
+            // other SGB players never press anything
+            res = 0xCF | self.value & 0x30;
+        } else if (self.value >> 5) & 0x1 == 0 {
             if self.a { res &= !(1 << 0) }
             if self.b { res &= !(1 << 1) }
             if self.select { res &= !(1 << 2) }
@@ -28,10 +44,17 @@ impl Memory for Joypad {
             if self.down { res &= !(1 << 3) }
             res &= !(1 << 4)
         }
+        if self.players > 1 && self.value & 0x30 == 0x30 {
+            // with no line selected the SGB tells which joypad comes next
+            res = 0xF0 | (0xF - self.player);
+        }
         res
     }
 
     fn set_byte(&mut self, _: u16, value: u8) {
+        if self.sgb {
+            self.sgb_lines(value & 0x30, self.value & 0x30);
+        }
         self.value = value;
     }
 }
@@ -48,7 +71,84 @@ impl Joypad {
             down: false,
             left: false,
             right: false,
+            sgb: false,
+            received: None,
+            packet: [0; 16],
+            packets: VecDeque::new(),
+            players: 1,
+            player: 0,
+        }
+    }
+
+    fn sgb_lines(&mut self, lines: u8, previous: u8) {
+        if lines == 0 {
+            self.received = Some(0);
+            self.packet = [0; 16];
+        } else if previous == 0x30 && lines != 0x30 {
+            match self.received {
+                Some(128) => {
+                    self.packets.push_back(self.packet);
+                    self.received = None;
+                }
+                Some(bit) => {
+                    if lines == 0x10 {
+                        self.packet[bit / 8] |= 1 << (bit % 8);
+                    }
+                    self.received = Some(bit + 1);
+                }
+                None => {}
+            }
+        } else if lines == 0x30 && previous == 0x10 && self.received.is_none() && self.players > 1 {
+            // deselecting the buttons moves on to the next joypad
+            self.player = (self.player + 1) % self.players;
+        }
+    }
+
+    // sets the number of SGB joypads, going back to the first one
+    pub fn set_players(&mut self, players: u8) {
+        self.players = players;
+        self.player = 0;
+    }
+
+}
+
+impl SaveState for Joypad {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.value);
+        for pressed in [self.a, self.b, self.select, self.start, self.up, self.down, self.left, self.right] {
+            w.bool(pressed);
+        }
+        w.bool(self.sgb);
+        w.bool(self.received.is_some());
+        w.u8(self.received.unwrap_or(0) as u8);
+        w.bytes(&self.packet);
+        w.u8(self.packets.len() as u8);
+        for packet in self.packets.iter() {
+            w.bytes(packet);
         }
+        w.u8(self.players);
+        w.u8(self.player);
     }
 
-}
\ No newline at end of file
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.value = r.u8()?;
+        for pressed in [&mut self.a, &mut self.b, &mut self.select, &mut self.start,
+                        &mut self.up, &mut self.down, &mut self.left, &mut self.right] {
+            *pressed = r.bool()?;
+        }
+        self.sgb = r.bool()?;
+        let receiving: bool = r.bool()?;
+        let received: usize = r.u8()? as usize;
+        self.received = if receiving { Some(received.min(128)) } else { None };
+        r.bytes_into(&mut self.packet)?;
+        self.packets.clear();
+        for _ in 0..r.u8()? {
+            let mut packet: [u8; 16] = [0; 16];
+            r.bytes_into(&mut packet)?;
+            self.packets.push_back(packet);
+        }
+        self.players = r.u8()?;
+        self.player = r.u8()?;
+        Ok(())
+    }
+}
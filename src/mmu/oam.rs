@@ -1,4 +1,5 @@
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 pub struct OAM {
     oam: [u8; 0xA0],
@@ -28,4 +29,14 @@ impl Memory for OAM {
             panic!("Unaccessible memory.")
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for OAM {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.oam);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.oam)
+    }
+}
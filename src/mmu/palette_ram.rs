@@ -0,0 +1,105 @@
+use crate::state::{SaveState, StateReader, StateWriter};
+
+// CGB colour palettes: 8 BG and 8 OBJ palettes of 4 little endian RGB555 colours,
+// reached through an index register (BCPS/OCPS, bit 7 auto-increments on data
+// writes) and a data register (BCPD/OCPD).
+pub struct PaletteRam {
+    bg: [u8; 64],
+    obj: [u8; 64],
+    bcps: u8,
+    ocps: u8,
+    cgb: bool,
+}
+
+impl Default for PaletteRam {
+    fn default() -> Self {
+        PaletteRam::new(false)
+    }
+}
+
+impl PaletteRam {
+    pub fn new(cgb: bool) -> Self {
+        PaletteRam { bg: [0xFF; 64], obj: [0xFF; 64], bcps: 0, ocps: 0, cgb }
+    }
+
+    pub fn get_byte(&self, address: u16) -> u8 {
+        if !self.cgb {
+            return 0xFF;
+        }
+        match address {
+            0xFF68 => self.bcps | 0x40,
+            0xFF69 => self.bg[(self.bcps & 0x3F) as usize],
+            0xFF6A => self.ocps | 0x40,
+            0xFF6B => self.obj[(self.ocps & 0x3F) as usize],
+            _ => panic!("Unaccessible memory.")
+        }
+    }
+
+    pub fn set_byte(&mut self, address: u16, value: u8) {
+        if !self.cgb {
+            return;
+        }
+        match address {
+            0xFF68 => self.bcps = value & 0xBF,
+            0xFF69 => {
+                self.bg[(self.bcps & 0x3F) as usize] = value;
+                self.bcps = PaletteRam::increment(self.bcps);
+            }
+            0xFF6A => self.ocps = value & 0xBF,
+            0xFF6B => {
+                self.obj[(self.ocps & 0x3F) as usize] = value;
+                self.ocps = PaletteRam::increment(self.ocps);
+            }
+            _ => panic!("Unaccessible memory.")
+        }
+    }
+
+    // a data access that is dropped while the PPU reads the palettes still increments the index
+    pub fn skip_write(&mut self, address: u16) {
+        match address {
+            0xFF69 => self.bcps = PaletteRam::increment(self.bcps),
+            0xFF6B => self.ocps = PaletteRam::increment(self.ocps),
+            _ => {}
+        }
+    }
+
+    // RGB555 colour `color` of BG palette `palette`
+    pub fn bg_color(&self, palette: u8, color: u8) -> u16 {
+        PaletteRam::color(&self.bg, palette, color)
+    }
+
+    // RGB555 colour `color` of OBJ palette `palette`
+    pub fn obj_color(&self, palette: u8, color: u8) -> u16 {
+        PaletteRam::color(&self.obj, palette, color)
+    }
+
+    fn color(ram: &[u8; 64], palette: u8, color: u8) -> u16 {
+        let i: usize = (palette as usize & 0x7) * 8 + (color as usize & 0x3) * 2;
+        (ram[i] as u16 | (ram[i + 1] as u16) << 8) & 0x7FFF
+    }
+
+    fn increment(index: u8) -> u8 {
+        if index & 0x80 != 0 {
+            0x80 | (index + 1) & 0x3F
+        } else {
+            index
+        }
+    }
+}
+
+impl SaveState for PaletteRam {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.bg);
+        w.bytes(&self.obj);
+        w.u8(self.bcps);
+        w.u8(self.ocps);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.bg)?;
+        r.bytes_into(&mut self.obj)?;
+        self.bcps = r.u8()?;
+        self.ocps = r.u8()?;
+        Ok(())
+    }
+}
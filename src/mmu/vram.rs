@@ -1,18 +1,59 @@
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
 // $8000 - $9FFF -- Video RAM (8192 Bytes)
 // $8000 - $9800 -- Character RAM (6144 Bytes)
 // $9800 - $9FFF -- BG display (2048 Bytes)
 // $9800 - $9BFF -- BG display MAP 1 (1024 Bytes)
 // $9C00 - $9FFF -- BG display MAP 2 (1024 Bytes)
+// On CGB a second bank, selected through VBK (0xFF4F), holds more tiles and the
+// BG map attributes at $9800 - $9FFF.
 pub struct VRam {
     bank0: [u8; 0x2000],
+    bank1: [u8; 0x2000],
+    cgb: bool,
+    // bank the CPU sees, VBK bit 0
+    bank: u8,
 }
 
 impl Default for VRam {
     fn default() -> Self {
+        VRam::new(false)
+    }
+}
+
+impl VRam {
+    pub fn new(cgb: bool) -> Self {
         VRam {
-            bank0: [0; 0x2000]
+            bank0: [0; 0x2000],
+            bank1: [0; 0x2000],
+            cgb,
+            bank: 0,
+        }
+    }
+
+    // number of 8kb banks, debug views go through all of them
+    pub fn banks(&self) -> u8 {
+        if self.cgb { 2 } else { 1 }
+    }
+
+    // reads from a given bank regardless of the one currently mapped
+    pub fn get_bank_byte(&self, bank: u8, address: u16) -> u8 {
+        match bank {
+            0 => self.bank0[(address - 0x8000) as usize],
+            1 if self.cgb => self.bank1[(address - 0x8000) as usize],
+            _ => panic!("Unaccessible VRAM bank {}.", bank)
+        }
+    }
+
+    // VBK, only bit 0 is used and the rest read back as 1
+    pub fn get_vbk(&self) -> u8 {
+        if self.cgb { 0xFE | self.bank } else { 0xFF }
+    }
+
+    pub fn set_vbk(&mut self, value: u8) {
+        if self.cgb {
+            self.bank = value & 0x1;
         }
     }
 }
@@ -20,7 +61,7 @@ impl Default for VRam {
 impl Memory for VRam {
     fn get_byte(&self, address: u16) -> u8 {
         if address < 0xA000 && address >= 0x8000 {
-            self.bank0[(address - 0x8000) as usize]
+            self.get_bank_byte(self.bank, address)
         } else {
             panic!("Unaccessible memory.")
         }
@@ -28,9 +69,28 @@ impl Memory for VRam {
 
     fn set_byte(&mut self, address: u16, value: u8) {
         if address < 0xA000 && address >= 0x8000 {
-            self.bank0[(address - 0x8000) as usize] = value;
+            if self.bank == 0 {
+                self.bank0[(address - 0x8000) as usize] = value;
+            } else {
+                self.bank1[(address - 0x8000) as usize] = value;
+            }
         } else {
             panic!("Unaccessible memory.")
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for VRam {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.bank0);
+        w.bytes(&self.bank1);
+        w.u8(self.bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.bytes_into(&mut self.bank0)?;
+        r.bytes_into(&mut self.bank1)?;
+        self.bank = r.u8()? & 0x1;
+        Ok(())
+    }
+}
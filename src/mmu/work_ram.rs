@@ -1,15 +1,39 @@
 use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
+// 0xC000 - 0xCFFF is always bank 0. On CGB SVBK (0xFF70) maps any of banks 1-7
+// at 0xD000 - 0xDFFF, other models only have bank 1.
 pub struct WorkRam {
-    bank0: [u8; 0x1000],
-    bank1: [u8; 0x1000],
+    banks: [[u8; 0x1000]; 8],
+    cgb: bool,
+    // bank mapped at 0xD000, 1 - 7
+    bank: usize,
 }
 
 impl Default for WorkRam {
     fn default() -> Self {
+        WorkRam::new(false)
+    }
+}
+
+impl WorkRam {
+    pub fn new(cgb: bool) -> Self {
         WorkRam {
-            bank0: [0; 0x1000],
-            bank1: [0; 0x1000],
+            banks: [[0; 0x1000]; 8],
+            cgb,
+            bank: 1,
+        }
+    }
+
+    // SVBK, bits 3-7 read back as 1
+    pub fn get_svbk(&self) -> u8 {
+        if self.cgb { 0xF8 | self.bank as u8 } else { 0xFF }
+    }
+
+    // selecting bank 0 maps bank 1
+    pub fn set_svbk(&mut self, value: u8) {
+        if self.cgb {
+            self.bank = ((value & 0x7) as usize).max(1);
         }
     }
 }
@@ -17,9 +41,9 @@ impl Default for WorkRam {
 impl Memory for WorkRam {
     fn get_byte(&self, address: u16) -> u8 {
         if address < 0xD000 && address >= 0xC000 {
-            self.bank0[(address - 0xC000) as usize]
+            self.banks[0][(address - 0xC000) as usize]
         } else if address < 0xE000 && address >= 0xD000 {
-            self.bank1[(address - 0xD000) as usize]
+            self.banks[self.bank][(address - 0xD000) as usize]
         } else {
             panic!("Unaccessible memory.")
         }
@@ -27,11 +51,28 @@ impl Memory for WorkRam {
 
     fn set_byte(&mut self, address: u16, value: u8) {
         if address < 0xD000 && address >= 0xC000 {
-            self.bank0[(address - 0xC000) as usize] = value;
+            self.banks[0][(address - 0xC000) as usize] = value;
         } else if address < 0xE000 && address >= 0xD000 {
-            self.bank1[(address - 0xD000) as usize] = value;
+            self.banks[self.bank][(address - 0xD000) as usize] = value;
         } else {
             panic!("Unaccessible memory.")
         }
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for WorkRam {
+    fn save_state(&self, w: &mut StateWriter) {
+        for bank in self.banks.iter() {
+            w.bytes(bank);
+        }
+        w.u8(self.bank as u8);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        for bank in self.banks.iter_mut() {
+            r.bytes_into(bank)?;
+        }
+        self.bank = ((r.u8()? & 0x7) as usize).max(1);
+        Ok(())
+    }
+}
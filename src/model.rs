@@ -0,0 +1,49 @@
+// Game Boy hardware revisions
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Model {
+    // original Game Boy, early boot ROM revision
+    Dmg0,
+    // original Game Boy
+    Dmg,
+    // Game Boy Pocket
+    Mgb,
+    // Super Game Boy
+    Sgb,
+    // Game Boy Color
+    Cgb,
+    // Game Boy Advance running Game Boy software
+    Agb,
+}
+
+impl Model {
+    pub fn from_name(name: &str) -> Model {
+        match name.to_lowercase().as_str() {
+            "dmg0" => Model::Dmg0,
+            "dmg" => Model::Dmg,
+            "mgb" => Model::Mgb,
+            "sgb" => Model::Sgb,
+            "cgb" => Model::Cgb,
+            "agb" => Model::Agb,
+            _ => panic!("unknown model: {}", name)
+        }
+    }
+
+    // model the cartridge header asks for: CGB flag at 0x143, SGB flag at 0x146
+    // (only honoured with the 0x33 old licensee code at 0x14B)
+    pub fn from_header(rom: &[u8]) -> Model {
+        let cgb_flag: u8 = *rom.get(0x143).unwrap_or(&0);
+        let sgb_flag: u8 = *rom.get(0x146).unwrap_or(&0);
+        let old_licensee: u8 = *rom.get(0x14B).unwrap_or(&0);
+        if cgb_flag & 0x80 != 0 {
+            Model::Cgb
+        } else if sgb_flag == 0x03 && old_licensee == 0x33 {
+            Model::Sgb
+        } else {
+            Model::Dmg
+        }
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        *self == Model::Cgb || *self == Model::Agb
+    }
+}
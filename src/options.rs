@@ -0,0 +1,82 @@
+use crate::mmu::compat;
+use crate::model::Model;
+use crate::ppu::palette::{ColorCorrection, Palette};
+
+pub struct Options {
+    pub rom: String,
+    // Gameboy Doctor trace output file, implies skip_boot and makes LY read 0x90
+    pub trace: Option<String>,
+    // start at 0x0100 with the post-boot state instead of running the boot ROM.
+    // Only the DMG boot ROM is embedded, every other model skips the boot unless
+    // `bootrom` is given.
+    pub skip_boot: bool,
+    // suggested from the cartridge header when not given
+    pub model: Option<Model>,
+    // boot ROM image for the selected model, the embedded one is DMG only
+    pub bootrom: Option<String>,
+    // DMG shades colours, a palette name or a palette file
+    pub palette: Palette,
+    // CGB colours conversion, none or cgb
+    pub color_correction: ColorCorrection,
+    // CGB palette combo held at power on for DMG cartridges when the boot is skipped,
+    // like up+a or right+b
+    pub compat_palette: Option<String>,
+    // the frontend writes screenshot-N.png once frame N is complete
+    pub screenshot_at: Option<u64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            rom: String::from("roms/Battletoads (Japan).gb"),
+            trace: None,
+            skip_boot: false,
+            model: None,
+            bootrom: None,
+            palette: Default::default(),
+            color_correction: Default::default(),
+            compat_palette: None,
+            screenshot_at: None,
+        }
+    }
+}
+
+impl Options {
+    // args as returned by std::env::args, the first one being the executable
+    pub fn from_args(args: &[String]) -> Options {
+        let mut options: Options = Default::default();
+        let mut it = args.iter().skip(1);
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--trace" => {
+                    options.trace = Some(it.next().expect("missing file for --trace").clone());
+                    options.skip_boot = true;
+                }
+                "--skip-boot" => options.skip_boot = true,
+                "--bootrom" => options.bootrom = Some(it.next().expect("missing file for --bootrom").clone()),
+                "--palette" => {
+                    let name: &String = it.next().expect("missing name or file for --palette");
+                    options.palette = Palette::from_name(name)
+                        .unwrap_or_else(|| Palette::load(name).unwrap_or_else(|e| panic!("{}", e)));
+                }
+                "--color-correction" => {
+                    options.color_correction = ColorCorrection::from_name(it.next().expect("missing name for --color-correction"))
+                }
+                "--compat-palette" => {
+                    let name: &String = it.next().expect("missing combo for --compat-palette");
+                    if compat::combo(name).is_none() {
+                        panic!("unknown palette combo: {}", name);
+                    }
+                    options.compat_palette = Some(name.clone());
+                }
+                "--screenshot-at-frame" => {
+                    let frame: &String = it.next().expect("missing frame for --screenshot-at-frame");
+                    options.screenshot_at = Some(frame.parse().unwrap_or_else(|_| panic!("invalid frame {}", frame)));
+                }
+                "--model" => options.model = Some(Model::from_name(it.next().expect("missing name for --model"))),
+                _ => options.rom = arg.clone(),
+            }
+        }
+        options
+    }
+}
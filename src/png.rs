@@ -0,0 +1,341 @@
+use crate::crc::crc32;
+
+// Minimal PNG support for screenshots and reference images. The encoder writes
+// 8-bit RGB images using stored (uncompressed) deflate blocks, which every PNG
+// reader accepts. The decoder handles the common non-interlaced formats.
+
+static SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+// largest payload of a stored deflate block
+static BLOCK_SIZE: usize = 0xFFFF;
+
+// `rgb` holds width * height pixels row by row, 3 bytes each
+pub fn encode(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    assert_eq!(rgb.len(), (width * height * 3) as usize, "image size does not match {}x{}", width, height);
+
+    let mut png: Vec<u8> = SIGNATURE.to_vec();
+
+    let mut header: Vec<u8> = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, colour type 2 (RGB), default compression, filter and no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // every row starts with filter type 0 (none)
+    let mut raw: Vec<u8> = Vec::with_capacity(rgb.len() + height as usize);
+    for row in rgb.chunks((width * 3) as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn write(path: &str, width: u32, height: u32, rgb: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, encode(width, height, rgb))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start: usize = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc: u32 = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate, 32K window, no preset dictionary
+    let mut out: Vec<u8> = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![data] } else { data.chunks(BLOCK_SIZE).collect() };
+    for (i, block) in blocks.iter().enumerate() {
+        out.push(if i == blocks.len() - 1 { 1 } else { 0 });
+        let len: u16 = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b): (u32, u32) = (1, 0);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+// decodes 8-bit RGB, RGBA, greyscale and indexed images (the last two also at
+// 1, 2 and 4 bits), returning width, height and RGB pixels row by row
+pub fn decode(png: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    if png.len() < 8 || png[..8] != SIGNATURE {
+        return Err(String::from("not a PNG file"));
+    }
+
+    let mut header: Option<[u8; 13]> = None;
+    let mut palette: Vec<u8> = Vec::new();
+    let mut compressed: Vec<u8> = Vec::new();
+    let mut i: usize = 8;
+    while i + 12 <= png.len() {
+        let len: usize = u32::from_be_bytes([png[i], png[i + 1], png[i + 2], png[i + 3]]) as usize;
+        let kind: &[u8] = &png[i + 4..i + 8];
+        let data: &[u8] = png.get(i + 8..i + 8 + len).ok_or("truncated chunk")?;
+        match kind {
+            b"IHDR" if len == 13 => {
+                let mut h: [u8; 13] = [0; 13];
+                h.copy_from_slice(data);
+                header = Some(h);
+            }
+            b"PLTE" => palette = data.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        i += 12 + len;
+    }
+
+    let header: [u8; 13] = header.ok_or("missing IHDR chunk")?;
+    let width: u32 = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height: u32 = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let (depth, color_type, interlace): (u8, u8, u8) = (header[8], header[9], header[12]);
+    if interlace != 0 {
+        return Err(String::from("interlaced images are not supported"));
+    }
+    let channels: usize = match (color_type, depth) {
+        (0, 1) | (0, 2) | (0, 4) | (0, 8) | (3, 1) | (3, 2) | (3, 4) | (3, 8) => 1,
+        (4, 8) => 2,
+        (2, 8) => 3,
+        (6, 8) => 4,
+        _ => return Err(format!("unsupported colour type {} with bit depth {}", color_type, depth))
+    };
+
+    if compressed.len() < 6 {
+        return Err(String::from("missing image data"));
+    }
+    let raw: Vec<u8> = inflate(&compressed[2..])?;
+
+    // bytes per complete pixel for the filters, at least one
+    let bpp: usize = (channels * depth as usize).div_ceil(8);
+    let stride: usize = (width as usize * channels * depth as usize).div_ceil(8);
+    if raw.len() < (stride + 1) * height as usize {
+        return Err(String::from("image data too short"));
+    }
+
+    let mut rgb: Vec<u8> = Vec::with_capacity((width * height * 3) as usize);
+    let mut previous: Vec<u8> = vec![0; stride];
+    for y in 0..height as usize {
+        let start: usize = y * (stride + 1);
+        let filter: u8 = raw[start];
+        let mut row: Vec<u8> = raw[start + 1..start + 1 + stride].to_vec();
+        for x in 0..stride {
+            let a: u8 = if x >= bpp { row[x - bpp] } else { 0 };
+            let b: u8 = previous[x];
+            let c: u8 = if x >= bpp { previous[x - bpp] } else { 0 };
+            row[x] = row[x].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("invalid filter type {}", filter))
+            });
+        }
+
+        for x in 0..width as usize {
+            match color_type {
+                0 | 3 => {
+                    let bit: usize = x * depth as usize;
+                    let shift: usize = 8 - depth as usize - bit % 8;
+                    let value: u8 = row[bit / 8] >> shift & ((1_u16 << depth) - 1) as u8;
+                    if color_type == 3 {
+                        let entry: &[u8] = palette.get(value as usize * 3..value as usize * 3 + 3).ok_or("colour missing from palette")?;
+                        rgb.extend_from_slice(entry);
+                    } else {
+                        let grey: u8 = (value as u16 * 255 / ((1_u16 << depth) - 1)) as u8;
+                        rgb.extend_from_slice(&[grey, grey, grey]);
+                    }
+                }
+                4 => rgb.extend_from_slice(&[row[x * 2], row[x * 2], row[x * 2]]),
+                _ => rgb.extend_from_slice(&row[x * channels..x * channels + 3]),
+            }
+        }
+        previous = row;
+    }
+    Ok((width, height, rgb))
+}
+
+pub fn read(path: &str) -> Result<(u32, u32, Vec<u8>), String> {
+    let data: Vec<u8> = std::fs::read(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+    decode(&data).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p: i16 = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc): (i16, i16, i16) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// reads deflate data least significant bit first
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u8) -> Result<u16, String> {
+        let mut value: u16 = 0;
+        for i in 0..count {
+            let byte: u8 = *self.data.get(self.position / 8).ok_or("unexpected end of deflate data")?;
+            value |= ((byte >> (self.position % 8) & 1) as u16) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+// canonical Huffman code: number of codes of every length and the symbols sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts: [u16; 16] = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = Vec::with_capacity(lengths.len());
+        for length in 1..16 {
+            for (symbol, l) in lengths.iter().enumerate() {
+                if *l as usize == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn symbol(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let (mut code, mut first, mut index): (i32, i32, i32) = (0, 0, 0);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count: i32 = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(String::from("invalid Huffman code"))
+    }
+}
+
+static LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+static LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+static DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+static DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// order the code length code lengths are stored in
+static CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// raw deflate stream (RFC 1951) without the zlib header
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader { data, position: 0 };
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let last: bool = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let len: u16 = reader.bits(16)?;
+                reader.bits(16)?;
+                let start: usize = reader.position / 8;
+                let block: &[u8] = data.get(start..start + len as usize).ok_or("truncated stored block")?;
+                out.extend_from_slice(block);
+                reader.position += len as usize * 8;
+            }
+            1 => {
+                let mut lengths: [u8; 288] = [8; 288];
+                lengths[144..256].iter_mut().for_each(|l| *l = 9);
+                lengths[256..280].iter_mut().for_each(|l| *l = 7);
+                inflate_block(&mut reader, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            }
+            2 => {
+                let literals: usize = reader.bits(5)? as usize + 257;
+                let distances: usize = reader.bits(5)? as usize + 1;
+                let code_lengths: usize = reader.bits(4)? as usize + 4;
+                let mut lengths: [u8; 19] = [0; 19];
+                for i in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+                    lengths[*i] = reader.bits(3)? as u8;
+                }
+                let code_length_code = Huffman::new(&lengths);
+
+                let mut lengths: Vec<u8> = Vec::with_capacity(literals + distances);
+                while lengths.len() < literals + distances {
+                    let (value, repeat): (u8, u16) = match code_length_code.symbol(&mut reader)? {
+                        symbol @ 0..=15 => (symbol as u8, 1),
+                        16 => (*lengths.last().ok_or("repeat without previous length")?, 3 + reader.bits(2)?),
+                        17 => (0, 3 + reader.bits(3)?),
+                        _ => (0, 11 + reader.bits(7)?),
+                    };
+                    lengths.extend(std::iter::repeat_n(value, repeat as usize));
+                }
+                if lengths.len() > literals + distances {
+                    return Err(String::from("too many code lengths"));
+                }
+                let literal_code = Huffman::new(&lengths[..literals]);
+                let distance_code = Huffman::new(&lengths[literals..]);
+                inflate_block(&mut reader, &mut out, &literal_code, &distance_code)?;
+            }
+            _ => return Err(String::from("invalid deflate block type"))
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol: u16 = literals.symbol(reader)?;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i: usize = symbol as usize - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(String::from("invalid length symbol"));
+                }
+                let length: usize = (LENGTH_BASE[i] + reader.bits(LENGTH_EXTRA[i])?) as usize;
+                let d: usize = distances.symbol(reader)? as usize;
+                if d >= DISTANCE_BASE.len() {
+                    return Err(String::from("invalid distance symbol"));
+                }
+                let distance: usize = (DISTANCE_BASE[d] + reader.bits(DISTANCE_EXTRA[d])?) as usize;
+                if distance > out.len() {
+                    return Err(String::from("distance too far back"));
+                }
+                let start: usize = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::mmu::bus::Bus;
+use crate::mmu::memory::Memory;
+use crate::ppu::fetcher::{tile_data_address, tile_row};
+use crate::ppu::oam_entry::OamEntry;
+use crate::ppu::palette::{ColorCorrection, Palette};
+use crate::ppu::ppu::Ppu;
+
+static LCDC: u16 = 0xFF40;
+static SCY: u16 = 0xFF42;
+static SCX: u16 = 0xFF43;
+static BGP: u16 = 0xFF47;
+static OBP0: u16 = 0xFF48;
+static OBP1: u16 = 0xFF49;
+static WY: u16 = 0xFF4A;
+static WX: u16 = 0xFF4B;
+
+static VIEWPORT_COLOR: [u8; 3] = [255, 0, 0];
+static WINDOW_COLOR: [u8; 3] = [0, 0, 255];
+
+// RGB image, row by row
+pub struct DebugImage {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl DebugImage {
+    pub fn new(width: usize, height: usize) -> DebugImage {
+        DebugImage { width, height, rgb: vec![0; width * height * 3] }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let i: usize = (y * self.width + x) * 3;
+        [self.rgb[i], self.rgb[i + 1], self.rgb[i + 2]]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        let i: usize = (y * self.width + x) * 3;
+        self.rgb[i..i + 3].copy_from_slice(&color);
+    }
+
+    // outline of a rectangle, wrapping around the edges like the tile maps do
+    fn draw_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for i in 0..width {
+            self.set_pixel((x + i) % self.width, y % self.height, color);
+            self.set_pixel((x + i) % self.width, (y + height - 1) % self.height, color);
+        }
+        for j in 0..height {
+            self.set_pixel(x % self.width, (y + j) % self.height, color);
+            self.set_pixel((x + width - 1) % self.width, (y + j) % self.height, color);
+        }
+    }
+}
+
+// all 384 tiles at 0x8000 - 0x97FF, 16 per row with their raw colour numbers,
+// shown through BG palette 0 on CGB. Every VRAM bank gets its own 128x192 block,
+// left to right.
+pub fn tiles(bus: &Bus, palette: &Palette, color_correction: ColorCorrection) -> DebugImage {
+    let banks: u8 = bus.vram.banks();
+    let mut image = DebugImage::new(128 * banks as usize, 192);
+    for bank in 0..banks {
+        for tile in 0..384_u16 {
+            let x: usize = bank as usize * 128 + (tile % 16) as usize * 8;
+            let y: usize = (tile / 16) as usize * 8;
+            for row in 0..8 {
+                let pixels: [u8; 8] = tile_row(bus, bank, 0x8000 + tile * 16 + row as u16 * 2, false);
+                for (i, color) in pixels.iter().enumerate() {
+                    let rgb: [u8; 3] = if bus.model.is_cgb() {
+                        color_correction.rgb(bus.palette_ram.bg_color(0, *color))
+                    } else {
+                        palette.color(*color)
+                    };
+                    image.set_pixel(x + i, y + row as usize, rgb);
+                }
+            }
+        }
+    }
+    image
+}
+
+// 256x256 render of the tile map at 0x9800 (map 0) or 0x9C00 (map 1) using the
+// current tile data addressing, coloured the way the PPU would: BGP on DMG, the
+// map attributes and BG palette RAM on CGB, BGP through BG palette 0 in
+// compatibility mode. The screen area is outlined when the map is the background
+// one and the visible part of the window when it is the window one.
+pub fn tile_map(bus: &Bus, palette: &Palette, color_correction: ColorCorrection, map: u8) -> DebugImage {
+    let lcdc: u8 = bus.get_byte(LCDC);
+    let cgb_mode: bool = Ppu::cgb_mode(bus);
+    let bgp: u8 = bus.get_byte(BGP);
+    let map_address: u16 = if map == 0 { 0x9800 } else { 0x9C00 };
+    let mut image = DebugImage::new(256, 256);
+    for i in 0..1024_u16 {
+        let tile: u8 = bus.vram.get_bank_byte(0, map_address + i);
+        // CGB map attributes: tile bank, X and Y flips
+        let attributes: u8 = if cgb_mode { bus.vram.get_bank_byte(1, map_address + i) } else { 0 };
+        let address: u16 = tile_data_address(lcdc, tile);
+        for row in 0..8 {
+            let line: u8 = if attributes >> 6 & 0x1 == 1 { 7 - row } else { row };
+            let pixels: [u8; 8] = tile_row(bus, attributes >> 3 & 0x1, address + line as u16 * 2, attributes >> 5 & 0x1 == 1);
+            for (j, color) in pixels.iter().enumerate() {
+                let shade: u8 = bgp >> (color * 2) & 0b11;
+                let rgb: [u8; 3] = if cgb_mode {
+                    color_correction.rgb(bus.palette_ram.bg_color(attributes & 0b111, *color))
+                } else if bus.model.is_cgb() {
+                    color_correction.rgb(bus.palette_ram.bg_color(0, shade))
+                } else {
+                    palette.color(shade)
+                };
+                image.set_pixel((i % 32) as usize * 8 + j, (i / 32) as usize * 8 + row as usize, rgb);
+            }
+        }
+    }
+
+    if lcdc >> 3 & 0x1 == map {
+        let scx: usize = bus.get_byte(SCX) as usize;
+        let scy: usize = bus.get_byte(SCY) as usize;
+        image.draw_rect(scx, scy, 160, 144, VIEWPORT_COLOR);
+    }
+    let wx: usize = bus.get_byte(WX) as usize;
+    let wy: usize = bus.get_byte(WY) as usize;
+    if lcdc >> 5 & 0x1 == 1 && lcdc >> 6 & 0x1 == map && wx < 167 && wy < 144 {
+        // columns cut off on the left when WX is below 7
+        let left: usize = 7_usize.saturating_sub(wx);
+        image.draw_rect(left, 0, 167 - wx.max(7), 144 - wy, WINDOW_COLOR);
+    }
+    image
+}
+
+// the 40 sprites, 8 per row, each in a 8x16 cell drawn with its flips and
+// palette, see sprite_color. 8x8 sprites leave the bottom half of the cell empty.
+pub fn oam_sheet(bus: &Bus, palette: &Palette, color_correction: ColorCorrection) -> DebugImage {
+    let mut image = DebugImage::new(64, 80);
+    let background: [u8; 3] = if bus.model.is_cgb() { color_correction.rgb(0x7FFF) } else { palette.color(0) };
+    for y in 0..80 {
+        for x in 0..64 {
+            image.set_pixel(x, y, background);
+        }
+    }
+    for entry in sprites(bus) {
+        let height: u8 = if entry.obj16 { 16 } else { 8 };
+        let x: usize = (entry.index % 8) as usize * 8;
+        let y: usize = (entry.index / 8) as usize * 16;
+        let bank: u8 = if entry.bank && Ppu::cgb_mode(bus) { 1 } else { 0 };
+        let tile: u16 = if entry.obj16 { (entry.tile & 0xFE) as u16 } else { entry.tile as u16 };
+        for row in 0..height {
+            let line: u8 = if entry.yflip { height - 1 - row } else { row };
+            let pixels: [u8; 8] = tile_row(bus, bank, 0x8000 + tile * 16 + line as u16 * 2, entry.xflip);
+            for (i, color) in pixels.iter().enumerate() {
+                if *color != 0 {
+                    image.set_pixel(x + i, y + row as usize, sprite_color(bus, &entry, *color, palette, color_correction));
+                }
+            }
+        }
+    }
+    image
+}
+
+// attributes of the 40 sprites in OAM order, sprite_color gives their colours
+pub fn sprites(bus: &Bus) -> Vec<OamEntry> {
+    let obj16: bool = bus.get_byte(LCDC) >> 2 & 0x1 == 1;
+    (0..40_u16).map(|i| {
+        let address: u16 = 0xFE00 + i * 4;
+        let oam: [u8; 4] = [
+            bus.oam.get_byte(address),
+            bus.oam.get_byte(address + 1),
+            bus.oam.get_byte(address + 2),
+            bus.oam.get_byte(address + 3),
+        ];
+        OamEntry::new(i as u8, oam, obj16)
+    }).collect()
+}
+
+// RGB of colour number `color` of a sprite as the PPU draws it: OBP0/OBP1 on DMG,
+// the OBJ palette RAM entry picked by attribute bits 0-2 on CGB, OBP0/OBP1 through
+// OBJ palette 0 or 1 in compatibility mode
+pub fn sprite_color(bus: &Bus, entry: &OamEntry, color: u8, palette: &Palette, color_correction: ColorCorrection) -> [u8; 3] {
+    if Ppu::cgb_mode(bus) {
+        return color_correction.rgb(bus.palette_ram.obj_color(entry.cgb_palette, color));
+    }
+    let obp: u8 = bus.get_byte(if entry.palette { OBP1 } else { OBP0 });
+    let shade: u8 = obp >> (color * 2) & 0b11;
+    if bus.model.is_cgb() {
+        color_correction.rgb(bus.palette_ram.obj_color(entry.palette as u8, shade))
+    } else {
+        palette.color(shade)
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+
+use crate::mmu::bus::Bus;
+use crate::state::{SaveState, StateReader, StateWriter};
+
+static LCDC: u16 = 0xFF40;
+static SCY: u16 = 0xFF42;
+static SCX: u16 = 0xFF43;
+
+// Background or window pixel, palettes are applied when it leaves the FIFO
+#[derive(Debug, Copy, Clone)]
+pub struct BgPixel {
+    pub color: u8,
+    // BG palette number from the CGB map attributes
+    pub palette: u8,
+    // CGB map attribute bit 7: drawn over sprites when its colour is not 0
+    pub priority: bool,
+}
+
+// Sprite pixel, colour 0 being transparent
+#[derive(Debug, Copy, Clone)]
+pub struct ObjPixel {
+    pub color: u8,
+    pub palette: bool,
+    pub cgb_palette: u8,
+    pub priority: bool,
+    // OAM index of the sprite it comes from
+    pub index: u8,
+}
+
+impl Default for ObjPixel {
+    fn default() -> Self {
+        ObjPixel { color: 0, palette: false, cgb_palette: 0, priority: false, index: 0xFF }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum FetcherState {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+// Background/window tile fetcher. Every step but Push takes 2 dots, Push waits
+// until the background FIFO is empty and then fills it with 8 pixels.
+pub struct Fetcher {
+    state: FetcherState,
+    ticks: u8,
+    // tile column, relative to SCX for the background or to the window start
+    x: u8,
+    pub window: bool,
+    // the first fetch of every line is thrown away
+    dummy: bool,
+    // reads the CGB map attributes from VRAM bank 1
+    cgb: bool,
+    tile: u8,
+    // CGB map attributes of the tile, 0 otherwise
+    attributes: u8,
+    low: u8,
+    high: u8,
+}
+
+impl Default for Fetcher {
+    fn default() -> Self {
+        Fetcher {
+            state: FetcherState::Tile,
+            ticks: 0,
+            x: 0,
+            window: false,
+            dummy: true,
+            cgb: false,
+            tile: 0,
+            attributes: 0,
+            low: 0,
+            high: 0,
+        }
+    }
+}
+
+impl Fetcher {
+    // restarts the fetcher at the beginning of a line
+    pub fn start_line(&mut self, cgb: bool) {
+        *self = Default::default();
+        self.cgb = cgb;
+    }
+
+    // restarts the fetcher at the first column of the window
+    pub fn start_window(&mut self) {
+        self.state = FetcherState::Tile;
+        self.ticks = 0;
+        self.x = 0;
+        self.window = true;
+    }
+
+    // true once the tile data is fetched and only waiting to be pushed. A sprite
+    // fetch can already start on the last dot of it.
+    pub fn ready(&self) -> bool {
+        self.state == FetcherState::Push || (self.state == FetcherState::DataHigh && self.ticks == 1)
+    }
+
+    pub fn step(&mut self, bus: &Bus, ly: u8, window_line: u8, fifo: &mut VecDeque<BgPixel>) {
+        if self.state == FetcherState::Push {
+            if fifo.is_empty() {
+                let xflip: bool = self.attributes >> 5 & 0x1 == 1;
+                for color in row_colors(self.low, self.high, xflip).iter() {
+                    fifo.push_back(BgPixel {
+                        color: *color,
+                        palette: self.attributes & 0b111,
+                        priority: self.attributes >> 7 & 0x1 == 1,
+                    });
+                }
+                self.x = self.x.wrapping_add(1);
+                self.state = FetcherState::Tile;
+            }
+            return;
+        }
+
+        self.ticks += 1;
+        if self.ticks < 2 {
+            return;
+        }
+        self.ticks = 0;
+
+        let lcdc: u8 = bus.get_byte(LCDC);
+        match self.state {
+            FetcherState::Tile => {
+                let (map, column, row): (bool, u8, u8) = if self.window {
+                    (lcdc >> 6 & 0x1 == 1, self.x, window_line / 8)
+                } else {
+                    let scx: u8 = bus.get_byte(SCX);
+                    let scy: u8 = bus.get_byte(SCY);
+                    (lcdc >> 3 & 0x1 == 1, (scx / 8).wrapping_add(self.x) & 0x1F, ly.wrapping_add(scy) / 8)
+                };
+                let map_address: u16 = if map { 0x9C00 } else { 0x9800 };
+                let address: u16 = map_address + row as u16 * 32 + column as u16;
+                self.tile = bus.vram.get_bank_byte(0, address);
+                self.attributes = if self.cgb { bus.vram.get_bank_byte(1, address) } else { 0 };
+                self.state = FetcherState::DataLow;
+            }
+            FetcherState::DataLow => {
+                self.low = bus.vram.get_bank_byte(self.bank(), self.tile_address(bus, lcdc, ly, window_line));
+                self.state = FetcherState::DataHigh;
+            }
+            FetcherState::DataHigh => {
+                self.high = bus.vram.get_bank_byte(self.bank(), self.tile_address(bus, lcdc, ly, window_line) + 1);
+                if self.dummy {
+                    self.dummy = false;
+                    self.state = FetcherState::Tile;
+                } else {
+                    self.state = FetcherState::Push;
+                }
+            }
+            FetcherState::Push => {}
+        }
+    }
+
+    // VRAM bank of the tile data, CGB map attribute bit 3
+    fn bank(&self) -> u8 {
+        self.attributes >> 3 & 0x1
+    }
+
+    fn tile_address(&self, bus: &Bus, lcdc: u8, ly: u8, window_line: u8) -> u16 {
+        let mut row: u8 = if self.window {
+            window_line % 8
+        } else {
+            ly.wrapping_add(bus.get_byte(SCY)) % 8
+        };
+        // CGB map attribute bit 6
+        if self.attributes >> 6 & 0x1 == 1 {
+            row = 7 - row;
+        }
+        tile_data_address(lcdc, self.tile) + row as u16 * 2
+    }
+}
+
+// address of background or window tile `tile` with the addressing selected by
+// LCDC bit 4: unsigned from 0x8000 or signed from 0x9000
+pub fn tile_data_address(lcdc: u8, tile: u8) -> u16 {
+    if lcdc >> 4 & 0x1 == 1 {
+        0x8000 + tile as u16 * 16
+    } else {
+        0x9000_u16.wrapping_add((tile as i8 as i16 * 16) as u16)
+    }
+}
+
+// colour numbers of the tile row at `address` in VRAM bank `bank`, left to right
+pub fn tile_row(bus: &Bus, bank: u8, address: u16, xflip: bool) -> [u8; 8] {
+    row_colors(bus.vram.get_bank_byte(bank, address), bus.vram.get_bank_byte(bank, address + 1), xflip)
+}
+
+fn row_colors(low: u8, high: u8, xflip: bool) -> [u8; 8] {
+    let mut result: [u8; 8] = [0; 8];
+    for i in 0..8 {
+        let x: usize = if xflip { i } else { 7 - i };
+        result[x] = (high >> i & 0x1) << 1 | (low >> i & 0x1);
+    }
+    result
+}
+
+impl SaveState for BgPixel {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.color);
+        w.u8(self.palette);
+        w.bool(self.priority);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.color = r.u8()?;
+        self.palette = r.u8()?;
+        self.priority = r.bool()?;
+        Ok(())
+    }
+}
+
+impl SaveState for ObjPixel {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.color);
+        w.bool(self.palette);
+        w.u8(self.cgb_palette);
+        w.bool(self.priority);
+        w.u8(self.index);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.color = r.u8()?;
+        self.palette = r.bool()?;
+        self.cgb_palette = r.u8()?;
+        self.priority = r.bool()?;
+        self.index = r.u8()?;
+        Ok(())
+    }
+}
+
+impl SaveState for Fetcher {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(match self.state {
+            FetcherState::Tile => 0,
+            FetcherState::DataLow => 1,
+            FetcherState::DataHigh => 2,
+            FetcherState::Push => 3,
+        });
+        w.u8(self.ticks);
+        w.u8(self.x);
+        w.bool(self.window);
+        w.bool(self.dummy);
+        w.bool(self.cgb);
+        w.u8(self.tile);
+        w.u8(self.attributes);
+        w.u8(self.low);
+        w.u8(self.high);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.state = match r.u8()? {
+            0 => FetcherState::Tile,
+            1 => FetcherState::DataLow,
+            2 => FetcherState::DataHigh,
+            3 => FetcherState::Push,
+            s => return Err(format!("invalid fetcher state {}", s)),
+        };
+        self.ticks = r.u8()?;
+        self.x = r.u8()?;
+        self.window = r.bool()?;
+        self.dummy = r.bool()?;
+        self.cgb = r.bool()?;
+        self.tile = r.u8()?;
+        self.attributes = r.u8()?;
+        self.low = r.u8()?;
+        self.high = r.u8()?;
+        Ok(())
+    }
+}
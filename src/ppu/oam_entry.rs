@@ -1,116 +1,97 @@
 use crate::mmu::bus::Bus;
+use crate::mmu::memory::Memory;
+use crate::state::{SaveState, StateReader, StateWriter};
 
+#[derive(Debug, Copy, Clone)]
 pub struct OamEntry {
+    // position in OAM, 0 - 39
+    pub index: u8,
     pub ypos: u8,
     pub xpos: u8,
+    pub tile: u8,
+    // bit 7: BG and window colours 1-3 are drawn over the sprite
     pub priority: bool,
+    // bit 6
+    pub yflip: bool,
+    // bit 5
+    pub xflip: bool,
+    // bit 4: OBP1 instead of OBP0, DMG only
     pub palette: bool,
+    // bit 3: tile data from VRAM bank 1, CGB only
+    pub bank: bool,
+    // bits 0-2: OBJ palette number, CGB only
+    pub cgb_palette: u8,
     pub obj16: bool,
-    pub render8: [u8; 64],
-    pub render16: [u8; 128],
 }
 
 impl OamEntry {
-    pub fn new() -> Self {
+    pub fn new(index: u8, oam: [u8; 4], obj16: bool) -> Self {
+        let flags: u8 = oam[3];
         Self {
-            ypos: 0,
-            xpos: 0,
-
-            priority: false,
-            palette: false,
-            obj16: false,
-            render8: [0; 64],
-            render16: [0; 128],
+            index,
+            ypos: oam[0],
+            xpos: oam[1],
+            tile: oam[2],
+            priority: flags >> 7 & 0x1 == 1,
+            yflip: flags >> 6 & 0x1 == 1,
+            xflip: flags >> 5 & 0x1 == 1,
+            palette: flags >> 4 & 0x1 == 1,
+            bank: flags >> 3 & 0x1 == 1,
+            cgb_palette: flags & 0b111,
+            obj16,
         }
     }
 
+    // up to 10 sprites overlapping line `ly`, in OAM order. Sprites outside the
+    // screen horizontally still count towards the limit.
     pub fn get_oam_line(bus: &Bus, ly: u8, obj16: bool) -> Vec<OamEntry> {
+        let height: u16 = if obj16 { 16 } else { 8 };
         let mut ret = Vec::with_capacity(10);
-        let mut ctr = 0;
-        if obj16 {
-            for i in 0..20 {
-                if ctr >= 10 { break; }
-                let oam = {
-                    let mut result = [0; 8];
-                    for j in 0..8 {
-                        result[j as usize] = bus.get_byte(0xFE00 + (i * 4) + j);
-                    };
-                    result
-                };
-
-                let ypos: u8 = oam[0];
-                let xpos: u8 = oam[1];
-
-                if ypos == 0 || ypos >= 160 {
-                    continue;
-                }
-
-                if ly <= ypos - 0x10 || ly > ypos + 0x10 {
-                    println!("{:?} {:?}", ypos, ly);
-                    continue;
-                }
-
-                if xpos == 0 || xpos >= 168 {
-                    continue;
-                }
-
-                let mut oam_entry = OamEntry::new();
-                oam_entry.xpos = xpos;
-                oam_entry.ypos = ypos;
-                oam_entry.obj16 = true;
-                ctr += 1;
-                for i in 0..8 {
-                    let low = bus.get_byte(0x8000_u16.wrapping_add(oam[2] as u16 * 16).wrapping_add(i * 2));
-                    let top = bus.get_byte(0x8000_u16.wrapping_add(oam[2] as u16 * 16).wrapping_add(i * 2)).wrapping_add(1);
-                    for j in (0..8).rev() {
-                        oam_entry.render16[((7 - j) + 8 * i) as usize] = ((top >> j & 0x1) << 1 | (low >> j & 0x1));
-                    };
-                    let low = bus.get_byte(0x8000_u16.wrapping_add(oam[6] as u16 * 16).wrapping_add(i * 2));
-                    let top = bus.get_byte(0x8000_u16.wrapping_add(oam[6] as u16 * 16).wrapping_add(i * 2)).wrapping_add(1);
-                    for j in (0..8).rev() {
-                        oam_entry.render16[((7 - j) + 8 * i + 64) as usize] = ((top >> j & 0x1) << 1 | (low >> j & 0x1));
-                    };
-                };
-
-                ret.push(oam_entry);
+        for i in 0..40 {
+            if ret.len() >= 10 { break; }
+            let address: u16 = 0xFE00 + i * 4;
+            let ypos: u8 = bus.oam.get_byte(address);
+            let top: u16 = ly as u16 + 16;
+            if top < ypos as u16 || top >= ypos as u16 + height {
+                continue;
             }
-        } else {
-            for i in 0..40 {
-                if ctr >= 10 { break; }
-                let oam = {
-                    let mut result = [0; 4];
-                    for j in 0..4 {
-                        result[j as usize] = bus.get_byte(0xFE00 + (i * 4) + j);
-                    };
-                    result
-                };
-
-                let ypos: u8 = oam[0];
-                let xpos: u8 = oam[1];
-                if ypos == 0 || ypos >= 160 || ly + 0x10 > ypos || ly + 0x18 <= ypos {
-                    continue;
-                }
+            let oam: [u8; 4] = [
+                ypos,
+                bus.oam.get_byte(address + 1),
+                bus.oam.get_byte(address + 2),
+                bus.oam.get_byte(address + 3),
+            ];
+            ret.push(OamEntry::new(i as u8, oam, obj16));
+        }
+        ret
+    }
 
-                if xpos == 0 || xpos >= 168 {
-                    continue;
-                }
+    // tile data address of the sprite row drawn on line `ly`. In 8x16 mode the
+    // top half uses the even tile and the bottom half the following one.
+    pub fn row_address(&self, ly: u8) -> u16 {
+        let height: u16 = if self.obj16 { 16 } else { 8 };
+        let mut row: u16 = ly as u16 + 16 - self.ypos as u16;
+        if self.yflip {
+            row = height - 1 - row;
+        }
+        let tile: u16 = if self.obj16 { (self.tile & 0xFE) as u16 } else { self.tile as u16 };
+        0x8000 + tile * 16 + row * 2
+    }
+}
 
-                let mut oam_entry = OamEntry::new();
-                oam_entry.xpos = xpos;
-                oam_entry.ypos = ypos;
-                oam_entry.obj16 = false;
-                ctr += 1;
-                for i in 0..8 {
-                    let low = bus.get_byte(0x8000_u16.wrapping_add(oam[2] as u16 * 16).wrapping_add(i * 2));
-                    let top = bus.get_byte(0x8000_u16.wrapping_add(oam[2] as u16 * 16).wrapping_add(i * 2)).wrapping_add(1);
-                    for j in (0..8).rev() {
-                        oam_entry.render8[((7 - j) + 8 * i) as usize] = ((top >> j & 0x1) << 1 | (low >> j & 0x1));
-                    };
-                };
+impl SaveState for OamEntry {
+    fn save_state(&self, w: &mut StateWriter) {
+        let flags: u8 = (self.priority as u8) << 7 | (self.yflip as u8) << 6 | (self.xflip as u8) << 5
+            | (self.palette as u8) << 4 | (self.bank as u8) << 3 | self.cgb_palette;
+        w.bytes(&[self.index, self.ypos, self.xpos, self.tile, flags]);
+        w.bool(self.obj16);
+    }
 
-                ret.push(oam_entry);
-            }
-        }
-        ret
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let mut fields: [u8; 5] = [0; 5];
+        r.bytes_into(&mut fields)?;
+        let obj16: bool = r.bool()?;
+        *self = OamEntry::new(fields[0], [fields[1], fields[2], fields[3], fields[4]], obj16);
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,102 @@
+// Colours the four DMG shades are displayed with, lightest first
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Palette {
+    pub colors: [[u8; 3]; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::green()
+    }
+}
+
+impl Palette {
+    pub fn new(colors: [[u8; 3]; 4]) -> Palette {
+        Palette { colors }
+    }
+
+    // original Game Boy LCD
+    pub fn green() -> Palette {
+        Palette::new([[155, 188, 15], [132, 172, 15], [48, 98, 48], [15, 56, 15]])
+    }
+
+    pub fn greyscale() -> Palette {
+        Palette::new([[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]])
+    }
+
+    // Game Boy Pocket LCD
+    pub fn pocket() -> Palette {
+        Palette::new([[196, 207, 161], [139, 149, 109], [77, 83, 60], [31, 31, 31]])
+    }
+
+    // Game Boy Light backlit LCD
+    pub fn light() -> Palette {
+        Palette::new([[0, 206, 156], [0, 154, 115], [0, 103, 77], [0, 52, 38]])
+    }
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        match name.to_lowercase().as_str() {
+            "green" => Some(Palette::green()),
+            "greyscale" | "grayscale" => Some(Palette::greyscale()),
+            "pocket" => Some(Palette::pocket()),
+            "light" => Some(Palette::light()),
+            _ => None
+        }
+    }
+
+    // palette file: four RRGGBB hex colours, lightest first, one per line.
+    // A leading # is optional, empty lines and lines starting with ; are ignored.
+    pub fn load(path: &str) -> Result<Palette, String> {
+        let text: String = std::fs::read_to_string(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+        let mut colors: Vec<[u8; 3]> = Vec::with_capacity(4);
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with(';')) {
+            let hex: &str = line.strip_prefix('#').unwrap_or(line);
+            let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)
+                .ok_or_else(|| format!("invalid colour {} in {}", line, path))?;
+            colors.push([(value >> 16) as u8, (value >> 8) as u8, value as u8]);
+        }
+        if colors.len() != 4 {
+            return Err(format!("{} has {} colours, expected 4", path, colors.len()));
+        }
+        Ok(Palette::new([colors[0], colors[1], colors[2], colors[3]]))
+    }
+
+    pub fn color(&self, shade: u8) -> [u8; 3] {
+        self.colors[(shade & 0b11) as usize]
+    }
+}
+
+// How CGB RGB555 colours are turned into 8-bit RGB
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub enum ColorCorrection {
+    // channels scaled linearly, what the acid2 reference images use
+    #[default]
+    None,
+    // mixes the channels and darkens the output to approximate the washed out CGB LCD
+    Cgb,
+}
+
+impl ColorCorrection {
+    pub fn from_name(name: &str) -> ColorCorrection {
+        match name.to_lowercase().as_str() {
+            "none" => ColorCorrection::None,
+            "cgb" => ColorCorrection::Cgb,
+            _ => panic!("unknown colour correction: {}", name)
+        }
+    }
+
+    // `color` holds red in bits 0-4, green in bits 5-9 and blue in bits 10-14
+    pub fn rgb(&self, color: u16) -> [u8; 3] {
+        let r: u16 = color & 0x1F;
+        let g: u16 = color >> 5 & 0x1F;
+        let b: u16 = color >> 10 & 0x1F;
+        match self {
+            ColorCorrection::None => [(r << 3 | r >> 2) as u8, (g << 3 | g >> 2) as u8, (b << 3 | b >> 2) as u8],
+            ColorCorrection::Cgb => [
+                ((r * 13 + g * 2 + b) >> 1) as u8,
+                ((g * 3 + b) << 1) as u8,
+                ((r * 3 + g * 2 + b * 11) >> 1) as u8,
+            ],
+        }
+    }
+}
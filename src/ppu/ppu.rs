@@ -1,25 +1,71 @@
+use std::collections::VecDeque;
+
 use crate::mmu::bus::Bus;
+use crate::model::Model;
+use crate::ppu::fetcher::{tile_row, BgPixel, Fetcher, ObjPixel};
 use crate::ppu::oam_entry::OamEntry;
+use crate::ppu::palette::{ColorCorrection, Palette};
+use crate::state::{SaveState, StateReader, StateWriter};
 
 static LCDC: u16 = 0xFF40;
 static STAT: u16 = 0xFF41;
-static SCY: u16 = 0xFF42;
-static SCX: u16 = 0xFF43;
-static LY: u16 = 0xFF44;
+static LYC: u16 = 0xFF45;
 static BGP: u16 = 0xFF47;
+static OBP0: u16 = 0xFF48;
+static OBP1: u16 = 0xFF49;
 static WY: u16 = 0xFF4A;
 static WX: u16 = 0xFF4B;
 static IF: u16 = 0xFF0F;
 
+// dots per line, every CPU M-cycle is 4 dots
+static LINE_DOTS: u16 = 456;
+static OAM_SEARCH_DOTS: u16 = 80;
+static FRAME_DOTS: u32 = 70224;
+
 pub struct Ppu {
-    pub image: [u8; 160 * 144],
-    pub bg: [u8; 256 * 256],
-    pub bg2: [u8; 256 * 256],
+    pub model: Model,
+    // every pixel as a RGB555 colour on CGB, as a shade 0 - 3 on the other models
+    pub image: [u16; 160 * 144],
+    // colours the DMG shades are turned into by rgb_frame and rgba_frame
+    pub palette: Palette,
+    // how rgb_frame and rgba_frame convert CGB colours
+    pub color_correction: ColorCorrection,
     pub ready: bool,
+    // frames handed out so far, blank ones included
+    pub frames: u64,
     oam_entries: Vec<OamEntry>,
+    mode: VideoMode,
+    // current line, LY mirrors it
+    line: u8,
+    // dot within the current line, 0 - 455
+    dot: u16,
+    // pixels already sent to the LCD on the current line
+    lx: u8,
+    // background pixels still to drop at the start of the line for SCX fine scroll
+    discard: u8,
+    fetcher: Fetcher,
+    bg_fifo: VecDeque<BgPixel>,
+    obj_fifo: VecDeque<ObjPixel>,
+    // sprite being fetched, pixel output is stalled meanwhile
+    obj_fetch: Option<OamEntry>,
+    obj_fetch_ticks: u8,
+    // window row to draw next, only advances on lines where the window was drawn
+    window_line: u8,
+    // LY matched WY at the start of a line during this frame
+    wy_triggered: bool,
+    // window started at WX=166, so it covers the whole next line
+    window_wraps: bool,
+    // all enabled STAT sources ORed together, the interrupt fires on its rising edge
+    stat_line: bool,
+    // LCDC bit 7 as seen on the previous tick
+    lcd_on: bool,
+    // the first frame after turning the LCD on is not shown
+    skip_frame: bool,
+    // dots since the last blank frame while the LCD is off
+    off_dots: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 enum VideoMode {
     HBLANK = 0,
     VBLANK = 1,
@@ -28,481 +74,387 @@ enum VideoMode {
 }
 
 impl Ppu {
-    pub fn new() -> Self {
-        Self { image: [0; 160 * 144], bg: [0; 256 * 256], bg2: [0; 256 * 256], ready: false, oam_entries: Vec::with_capacity(10) }
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            image: [if model.is_cgb() { 0x7FFF } else { 0 }; 160 * 144],
+            palette: Default::default(),
+            color_correction: Default::default(),
+            ready: false,
+            frames: 0,
+            oam_entries: Vec::with_capacity(10),
+            // the LCD is off at power on
+            mode: VideoMode::HBLANK,
+            line: 0,
+            dot: 0,
+            lx: 0,
+            discard: 0,
+            fetcher: Default::default(),
+            bg_fifo: VecDeque::with_capacity(16),
+            obj_fifo: VecDeque::with_capacity(8),
+            obj_fetch: None,
+            obj_fetch_ticks: 0,
+            window_line: 0,
+            wy_triggered: false,
+            window_wraps: false,
+            stat_line: false,
+            lcd_on: false,
+            skip_frame: false,
+            off_dots: 0,
+        }
     }
 
+    // the boot ROM hands over during the last VBLANK line
+    pub fn skip_boot(&mut self) {
+        self.mode = VideoMode::VBLANK;
+        self.line = 153;
+        self.dot = 4;
+        self.lcd_on = true;
+    }
+
+    // steps are dots
     pub fn tick(&mut self, bus: &mut Bus, steps: i32) -> i32 {
         if steps < 1 {
             return steps;
         };
 
         if !Ppu::lcdc_on(bus) {
+            if self.lcd_on {
+                self.lcd_on = false;
+                self.turn_off(bus);
+            }
+            // keep handing out blank frames at the usual rate
+            self.off_dots += steps as u32;
+            if self.off_dots >= FRAME_DOTS {
+                self.off_dots -= FRAME_DOTS;
+                self.ready = true;
+                self.frames += 1;
+            }
             return 0;
         };
 
+        if !self.lcd_on {
+            self.lcd_on = true;
+            self.skip_frame = true;
+        }
 
-        let lyc_flag = bus.io_registers.ly == bus.io_registers.lyc;
-        if lyc_flag {
-            bus.io_registers.stat |= (1 << 2);
-        } else {
-            bus.io_registers.stat &= !(1 << 2);
+        for _ in 0..steps {
+            self.step(bus);
         }
-        if lyc_flag && bus.get_byte(STAT) >> 6 & 0x1 == 1 {
-            bus.set_byte(IF, bus.get_byte(IF) | 0b10);
+        0
+    }
+
+    // the PPU stops and restarts from the top of line 0 once enabled again,
+    // meanwhile the LCD shows a blank (white) screen
+    fn turn_off(&mut self, bus: &Bus) {
+        self.mode = VideoMode::HBLANK;
+        self.line = 0;
+        self.dot = 0;
+        self.lx = 0;
+        self.fetcher.start_line(Ppu::cgb_mode(bus));
+        self.bg_fifo.clear();
+        self.obj_fifo.clear();
+        self.obj_fetch = None;
+        self.oam_entries.clear();
+        self.window_line = 0;
+        self.wy_triggered = false;
+        self.window_wraps = false;
+        self.stat_line = false;
+        self.off_dots = 0;
+        self.image = [self.blank_color(); 160 * 144];
+        self.ready = true;
+        self.frames += 1;
+    }
+
+    fn step(&mut self, bus: &mut Bus) {
+        // LY already reads 0 shortly after line 153 starts, and LYC is compared against that
+        if self.line == 153 && self.dot == 4 {
+            bus.io_registers.ly = 0;
         }
+        self.update_stat(bus);
 
-        let remaining: i32 = match self.get_video_mode(bus) {
+        match self.mode {
             VideoMode::OamSearch => {
-                if steps < 20 { return steps; }
-                self.do_oam_search(bus)
-            }
-            VideoMode::PixelTransfer => {
-                if steps < 43 { return steps; }
-                self.do_pixel_transfer(bus)
-            }
-            VideoMode::HBLANK => {
-                if steps < 51 { return steps; }
-                self.do_hblank(bus)
+                if self.dot == 0 {
+                    if self.line == bus.get_byte(WY) {
+                        self.wy_triggered = true;
+                    }
+                    self.do_oam_search(bus);
+                }
+                if self.dot == OAM_SEARCH_DOTS - 1 {
+                    self.start_pixel_transfer(bus);
+                }
             }
-            VideoMode::VBLANK => {
-                if steps < 456 { return steps; }
-                self.do_vblank(bus)
+            VideoMode::PixelTransfer => self.do_pixel_transfer(bus),
+            // the first line after the LCD is turned on has no OAM search, STAT
+            // reads mode 0 until drawing starts. Any other HBlank follows a drawn line.
+            VideoMode::HBLANK if self.lx == 0 => {
+                if self.dot == 0 && self.line == bus.get_byte(WY) {
+                    self.wy_triggered = true;
+                }
+                if self.dot == OAM_SEARCH_DOTS - 1 {
+                    self.start_pixel_transfer(bus);
+                }
             }
-        };
+            VideoMode::HBLANK | VideoMode::VBLANK => {}
+        }
 
-        remaining
+        self.dot += 1;
+        if self.dot == LINE_DOTS {
+            self.dot = 0;
+            self.next_line(bus);
+        }
     }
 
-    fn write_image(&mut self, bus: &Bus) {
-        self.ready = true;
-
-        // write bg map here
-        let bgp: u8 = bus.get_byte(BGP);
-        let mut palette: [u8; 4] = [0; 4];
-        for i in 0..4 {
-            palette[i] = bgp >> i * 2 & 0b11;
+    // refreshes the coincidence flag and the STAT interrupt line. Only a low to high
+    // transition requests an interrupt, so a source going active while another one
+    // already holds the line high is blocked.
+    fn update_stat(&mut self, bus: &mut Bus) {
+        let lyc_flag = bus.io_registers.ly == bus.get_byte(LYC);
+        if lyc_flag {
+            bus.io_registers.stat |= 1 << 2;
+        } else {
+            bus.io_registers.stat &= !(1 << 2);
         }
 
-        let starting_bg_tilemap: u16 = 0x9800;
-        let starting_bg_data: u16 = 0x8000;
-
-        for i in 0..1024 {
-            let tile_ptr: u8 = bus.get_byte(starting_bg_tilemap + i as u16);
-
-            let mut tiles: [u8; 16] = [0; 16];
-            for j in 0..16_u16 {
-                let address = starting_bg_data + tile_ptr as u16 * 16 + j;
-                tiles[j as usize] = bus.get_byte(address);
-            }
-
-            for (k, b) in tiles.chunks(2).enumerate() {
-                for (l, v) in self.combine_byte(b[0], b[1], &palette).iter().enumerate() {
-                    self.bg[(i % 32) * 8 + (k + (i / 32) * 8) * 256 + l] = v.clone();
-                }
-            }
-        };
-        for i in 0..1024 {
-            let tile_ptr: u8 = bus.get_byte(starting_bg_tilemap + i as u16);
+        let stat: u8 = bus.get_byte(STAT);
+        // the mode 2 source also goes high at the start of line 144
+        let oam: bool = self.mode == VideoMode::OamSearch || (self.line == 144 && self.dot == 0);
+        let line: bool = (stat >> 3 & 0x1 == 1 && self.mode == VideoMode::HBLANK)
+            || (stat >> 4 & 0x1 == 1 && self.mode == VideoMode::VBLANK)
+            || (stat >> 5 & 0x1 == 1 && oam)
+            || (stat >> 6 & 0x1 == 1 && lyc_flag);
+        if line && !self.stat_line {
+            bus.set_byte(IF, bus.get_byte(IF) | 0b10);
+        }
+        self.stat_line = line;
+    }
 
-            let bgdata = if tile_ptr < 128 {
-                0x9000_u16.wrapping_add(tile_ptr as u16 * 16)
-            } else {
-                0x8800_u16.wrapping_add((tile_ptr - 128) as u16 * 16)
-            };
+    fn next_line(&mut self, bus: &mut Bus) {
+        self.line += 1;
+        if self.line == 144 {
+            bus.set_byte(IF, bus.get_byte(IF) | 0b1);
+            self.set_video_mode(VideoMode::VBLANK, bus);
+            self.finish_frame();
+        } else if self.line == 154 {
+            self.line = 0;
+            self.window_line = 0;
+            self.wy_triggered = false;
+            self.window_wraps = false;
+        }
+        bus.io_registers.ly = self.line;
 
-            let mut tiles: [u8; 16] = [0; 16];
-            for j in 0..16_u16 {
-                let address = bgdata + j;
-                tiles[j as usize] = bus.get_byte(address);
-            }
+        if self.line < 144 {
+            self.set_video_mode(VideoMode::OamSearch, bus);
+        }
+    }
 
-            for (k, b) in tiles.chunks(2).enumerate() {
-                for (l, v) in self.combine_byte(b[0], b[1], &palette).iter().enumerate() {
-                    self.bg2[(i % 32) * 8 + (k + (i / 32) * 8) * 256 + l] = v.clone();
-                }
-            }
-        };
+    fn finish_frame(&mut self) {
+        self.ready = true;
+        self.frames += 1;
+        // the skipped frame leaves the blank image in place
+        self.skip_frame = false;
     }
 
-    pub fn get_image(&mut self) -> &[u8] {
+    pub fn get_image(&mut self) -> &[u16] {
         self.ready = false;
         &self.image
     }
 
-    fn do_oam_search(&mut self, bus: &mut Bus) -> i32 {
-        // TODO find how many are in line
-
-        let ly: u8 = self.get_line(bus);
-        let scy: u8 = bus.get_byte(SCY);
-        let lcdc: u8 = bus.get_byte(LCDC);
-        let double_size = lcdc >> 2 & 0x1 == 1;
-
-        self.oam_entries = OamEntry::get_oam_line(bus, ly.wrapping_add(scy), double_size);
-
-        self.set_video_mode(VideoMode::PixelTransfer, bus);
-        0
+    // current frame as 160x144 RGB bytes, row by row
+    pub fn rgb_frame(&self) -> Vec<u8> {
+        self.image.iter().flat_map(|pixel| self.pixel_rgb(*pixel)).collect()
     }
 
-    fn do_pixel_transfer(&mut self, bus: &mut Bus) -> i32 {
-        self.writeline(bus);
+    // current frame as 160x144 RGBA bytes, row by row
+    pub fn rgba_frame(&self) -> Vec<u8> {
+        let mut frame: Vec<u8> = Vec::with_capacity(160 * 144 * 4);
+        for pixel in self.image.iter() {
+            frame.extend_from_slice(&self.pixel_rgb(*pixel));
+            frame.push(0xFF);
+        }
+        frame
+    }
 
-        // mode0 interrupt enabled
-        if bus.get_byte(STAT) >> 3 & 0x1 == 1 {
-            bus.set_byte(IF, bus.get_byte(IF) | 0b10);
+    fn pixel_rgb(&self, pixel: u16) -> [u8; 3] {
+        if self.model.is_cgb() {
+            self.color_correction.rgb(pixel)
+        } else {
+            self.palette.color(pixel as u8)
         }
+    }
 
-        self.set_video_mode(VideoMode::HBLANK, bus);
-        0
+    // what the LCD shows while off: white
+    fn blank_color(&self) -> u16 {
+        if self.model.is_cgb() { 0x7FFF } else { 0 }
     }
 
-    fn writeline(&mut self, bus: &mut Bus) {
-        let ly: u8 = bus.get_byte(LY);
-        let scx: u8 = bus.get_byte(SCX);
-        let scy: u8 = bus.get_byte(SCY);
-        let lcdc: u8 = bus.get_byte(LCDC);
-        let wx: u8 = bus.get_byte(WX);
-        let wy: u8 = bus.get_byte(WY);
-        let bgp: u8 = bus.get_byte(BGP);
-        let obj1: u8 = bus.get_byte(0xFF48);
-        let obj2: u8 = bus.get_byte(0xFF49);
-
-        let win_tile_map: bool = lcdc >> 6 & 0x1 == 1;
-        let win_enabled: bool = lcdc >> 5 & 0x1 == 1;
-        let tile_data: bool = lcdc >> 4 & 0x1 == 1;
-        let bg_tile_map: bool = lcdc >> 3 & 0x1 == 1;
-        let obj_size: bool = lcdc >> 2 & 0x1 == 1;
-        let obj_enabled: bool = lcdc >> 1 & 0x1 == 1;
-        let obj_priority: bool = lcdc & 0x1 == 1;
-
-        let win_tile_map_address: u16 = if win_tile_map { 0x9C00 } else { 0x9800 };
-        let bg_tile_map_address: u16 = if bg_tile_map { 0x9C00 } else { 0x9800 };
-
-        let bgp_palette: [u8; 4] = {
-            let mut palette: [u8; 4] = [0; 4];
-            for i in 0..4 {
-                palette[i] = bgp >> i * 2 & 0b11;
-            }
-            palette
-        };
-        let obj1_palette: [u8; 4] = {
-            let mut palette: [u8; 4] = [0; 4];
-            for i in 0..4 {
-                palette[i] = obj1 >> i * 2 & 0b11;
-            }
-            palette
-        };
-        let obj2_palette: [u8; 4] = {
-            let mut palette: [u8; 4] = [0; 4];
-            for i in 0..4 {
-                palette[i] = obj2 >> i * 2 & 0b11;
-            }
-            palette
-        };
+    fn do_oam_search(&mut self, bus: &mut Bus) {
+        let double_size = bus.get_byte(LCDC) >> 2 & 0x1 == 1;
+        self.oam_entries = OamEntry::get_oam_line(bus, self.line, double_size);
+    }
 
-        let bg_line: [u8; 160] = {
-            let y: u8 = ((ly.wrapping_sub(scy)) / 8);
-            let y_offset = (ly.wrapping_sub(scy)) % 8;
-            let tiles = self.get_tiles(bus, y, bg_tile_map_address);
-            self.render_tiles(bus, tiles, y_offset, tile_data, bgp_palette)
-        };
+    fn start_pixel_transfer(&mut self, bus: &mut Bus) {
+        self.lx = 0;
+        self.discard = bus.get_byte(0xFF43) % 8;
+        self.fetcher.start_line(Ppu::cgb_mode(bus));
+        self.bg_fifo.clear();
+        self.obj_fifo.clear();
+        self.obj_fetch = None;
+        self.set_video_mode(VideoMode::PixelTransfer, bus);
 
-        let win_line: [u8; 160] = {
-            if !win_enabled { [0; 160] } else {
-                let y: u8 = ((ly.wrapping_sub(wy)) / 8);
-                let y_offset = (ly.wrapping_sub(wy)) % 8;
-                let tiles = self.get_tiles(bus, y, win_tile_map_address);
-                self.render_tiles(bus, tiles, y_offset, tile_data, bgp_palette)
+        if self.window_wraps {
+            self.window_wraps = false;
+            if bus.get_byte(LCDC) >> 5 & 0x1 == 1 {
+                self.fetcher.start_window();
             }
-        };
+        }
+    }
 
-        let obj_line: [u8; 160] = {
-            let mut res: [u8; 160] = [0; 160];
-            if !obj_enabled { (res) } else {
-                let y: u8 = ly.wrapping_sub(scy);
-                for entry in &self.oam_entries {
-                    let l: u8 = entry.ypos - 0x10 - ly;
-                    if entry.obj16 {
-                        let render = entry.render16;
-                        for j in 0..8 {
-                            let x = (entry.xpos - 0x08 + j) as usize;
-                            let u8 = render[(l * 8 + j) as usize] as usize;
-                            res[x] = if entry.palette {
-                                obj2_palette[u8]
-                            } else {
-                                obj1_palette[u8]
-                            };
-                        }
-                    }
+    fn do_pixel_transfer(&mut self, bus: &mut Bus) {
+        let ly: u8 = self.line;
+        let window_line: u8 = self.window_line;
+
+        // a sprite fetch waits for the background fetcher to have its tile ready,
+        // and starts on the dot it gets there
+        if let Some(entry) = &self.obj_fetch {
+            if !self.fetcher.ready() || self.bg_fifo.is_empty() {
+                self.fetcher.step(bus, ly, window_line, &mut self.bg_fifo);
+                if !self.fetcher.ready() || self.bg_fifo.is_empty() {
+                    return;
                 }
-                res
             }
-        };
-        self.oam_entries.clear();
-
-        let line: [u8; 160] = {
-            let mut v: [u8; 160] = bg_line.clone();
-            for i in 0..160 {
-                if win_enabled && ly >= wy {
-                    v[i] = win_line[i];
-                } else if obj_line[i] != 0 {
-                    v[i] = obj_line[i];
+            self.obj_fetch_ticks += 1;
+            if self.obj_fetch_ticks == 6 {
+                let pixels: [u8; 8] = self.fetch_obj_row(bus, entry);
+                let skip: usize = if entry.xpos < 8 { 8 - entry.xpos as usize } else { 0 };
+                while self.obj_fifo.len() < 8 {
+                    self.obj_fifo.push_back(Default::default());
                 }
-            }
-
-            v
-        };
-
-
-        for i in 0..160_u16 {
-            let idx: u16 = (ly as u16 * 160) + i;
-            self.image[idx as usize] = line[i as usize];
-        }
-    }
-
-    fn get_tiles(&self, bus: &Bus, y: u8, map_address: u16) -> [u8; 20] {
-        let wx: u8 = bus.get_byte(WX);
-        let mut tiles: [u8; 20] = [0; 20];
-        for i in 0..20_u16 {
-            let off: u16 = if (wx as u16 / 8) + i > 32 { (wx as u16 / 8) + i - 32 } else { (wx as u16 / 8) + i };
-            tiles[i as usize] = bus.get_byte(map_address.wrapping_add(y as u16 * 32).wrapping_add(off));
-        };
-        tiles
-    }
-
-    fn render_tiles(&self, bus: &Bus, tiles: [u8; 20], y_offset: u8, tile_data: bool, palette: [u8; 4]) -> [u8; 160] {
-        // every tile is 16 bytes
-        let mut pixels: [u8; 160] = [0; 160];
-        for (i, b) in tiles.iter().enumerate() {
-            let address = {
-                if tile_data {
-                    0x8000_u16.wrapping_add((*b as u16 * 16) as u16).wrapping_add((y_offset * 2) as u16)
-                } else {
-                    if *b <= 127 {
-                        0x9000_u16.wrapping_add(*b as u16 * 16).wrapping_add((y_offset * 2) as u16)
-                    } else {
-                        0x8800_u16.wrapping_add((*b - 128) as u16 * 16).wrapping_add((y_offset * 2) as u16)
+                // DMG: the first sprite fetched wins, which gives priority to the
+                // lowest X and then the lowest OAM index. CGB: lowest OAM index wins.
+                let cgb_mode: bool = Ppu::cgb_mode(bus);
+                for (i, color) in pixels.iter().enumerate().skip(skip) {
+                    let pixel = &mut self.obj_fifo[i - skip];
+                    if *color != 0 && (pixel.color == 0 || (cgb_mode && entry.index < pixel.index)) {
+                        *pixel = ObjPixel {
+                            color: *color,
+                            palette: entry.palette,
+                            cgb_palette: entry.cgb_palette,
+                            priority: entry.priority,
+                            index: entry.index,
+                        };
                     }
                 }
-            };
-            let top_byte: u8 = bus.get_byte(address);
-            let low_byte: u8 = bus.get_byte(address + 1u16);
-            for j in (0..8).rev() {
-                pixels[i * 8 + 7 - j] = palette[((top_byte >> j & 0x1) << 1 | (low_byte >> j & 0x1)) as usize];
-            };
-        };
-        pixels
-    }
-
+                self.obj_fetch = None;
+            }
+            return;
+        }
 
-    fn get_window_for_line(&self, bus: &Bus, line: u8) -> [u8; 160] {
         let lcdc: u8 = bus.get_byte(LCDC);
-        let wy: u8 = bus.get_byte(WY);
+
+        // window takes over the rest of the line once reached, its first fetch
+        // starting on this dot. WX below 7 starts it at the left edge with the
+        // first 7 - WX pixels cut off.
         let wx: u8 = bus.get_byte(WX);
+        if !self.fetcher.window && lcdc >> 5 & 0x1 == 1 && self.wy_triggered && self.lx + 7 >= wx {
+            self.bg_fifo.clear();
+            self.discard = 7_u8.saturating_sub(wx);
+            self.window_wraps = wx == 166;
+            self.fetcher.start_window();
+        }
 
-        let bg_tile_data: bool = lcdc >> 4 & 0x1 == 1;
-        let win_tile_map_address: u16 = if lcdc >> 5 & 0x1 == 0x1 { 0x9C00 } else { 0x9800 };
+        self.fetcher.step(bus, ly, window_line, &mut self.bg_fifo);
 
-        let bgp: u8 = bus.get_byte(BGP);
-        let mut palette: [u8; 4] = [0; 4];
-        for i in 0..4 {
-            palette[i] = bgp >> i * 2 & 0b11;
+        if self.bg_fifo.is_empty() {
+            return;
         }
 
+        if self.discard > 0 {
+            self.bg_fifo.pop_front();
+            self.discard -= 1;
+            return;
+        }
 
-        let x: u16 = ((line.wrapping_sub(wy)) / 8) as u16;
-        let mut tiles: [u8; 20] = [0; 20];
-        for i in 0..20 {
-            let off: u8 = if (wx / 8) + i > 32 { (wx / 8) + i - 32 } else { (wx / 8) + i };
-            tiles[i as usize] = bus.get_byte(win_tile_map_address + (x * 32) + off as u16);
-        };
-
-        // every tile is 16 bytes
-        let x_offset = (line.wrapping_sub(wy)) % 8;
-        let mut pixels: [u8; 160] = [0; 160];
-        for (i, b) in tiles.iter().enumerate() {
-            let x = {
-                if bg_tile_data {
-                    0x8000_u16.wrapping_add((*b as u16 * 16) as u16).wrapping_add((x_offset * 2) as u16)
-                } else {
-                    if *b <= 127 {
-                        0x9000_u16.wrapping_add(*b as u16 * 16).wrapping_add((x_offset * 2) as u16)
-                    } else {
-                        0x8800_u16.wrapping_add((*b - 128) as u16 * 16).wrapping_add((x_offset * 2) as u16)
-                    }
-                }
-            };
-            let result = self.combine_byte(bus.get_byte(x), bus.get_byte(x + 1u16), &palette);
-            for j in 0..result.len() {
-                pixels[i * 8 + j] = result[j];
+        // sprites due at this pixel are fetched lowest X first, then in OAM order,
+        // which is what DMG priority relies on when several start left of the screen
+        if lcdc >> 1 & 0x1 == 1 {
+            let due = self.oam_entries.iter().enumerate().filter(|(_, e)| e.xpos <= self.lx + 8);
+            if let Some((i, _)) = due.min_by_key(|(_, e)| e.xpos) {
+                self.obj_fetch = Some(self.oam_entries.remove(i));
+                // the fetch already counts this dot if the background fetcher is done
+                self.obj_fetch_ticks = self.fetcher.ready() as u8;
+                return;
             }
-        };
-        pixels
-    }
-
-    fn get_background_for_line(&self, bus: &Bus, line: u8) -> [u8; 160] {
-        let lcdc: u8 = bus.get_byte(LCDC);
-        let scx: u8 = bus.get_byte(SCX);
-        let bgp: u8 = bus.get_byte(BGP);
-
-        let bg_tile_data: bool = lcdc >> 4 & 0x1 == 1;
-        let bg_tile_option: bool = lcdc >> 3 & 0x1 == 1;
-
-        let mut palette: [u8; 4] = [0; 4];
-        for i in 0..4 {
-            palette[i] = bgp >> i * 2 & 0b11;
         }
 
-        let x: u16 = (line / 8) as u16;
-
-        let mut tiles: [u8; 20] = [0; 20];
-        let starting_bg_tilemap: u16 = if bg_tile_option { 0x9C00 } else { 0x9800 };
-        for i in 0..20 {
-            let off: u8 = if (scx / 8) + i > 32 { (scx / 8) + i - 32 } else { (scx / 8) + i };
-            tiles[i as usize] = bus.get_byte(starting_bg_tilemap + (x * 32) + off as u16);
+        let bg: BgPixel = self.bg_fifo.pop_front().unwrap();
+        let obj: Option<ObjPixel> = self.obj_fifo.pop_front();
+
+        // LCDC bit 0 blanks BG and window on DMG, on CGB it takes away their priority over sprites
+        let cgb_mode: bool = Ppu::cgb_mode(bus);
+        let bg_color: u8 = if cgb_mode || lcdc & 0x1 == 1 { bg.color } else { 0 };
+        let obj_visible = |o: &ObjPixel| -> bool {
+            if o.color == 0 || lcdc >> 1 & 0x1 == 0 {
+                false
+            } else if bg_color == 0 {
+                true
+            } else if cgb_mode {
+                lcdc & 0x1 == 0 || !(bg.priority || o.priority)
+            } else {
+                !o.priority
+            }
         };
-
-        // every tile is 16 bytes
-        let x_offset = line % 8;
-        let mut pixels: [u8; 160] = [0; 160];
-        for (i, b) in tiles.iter().enumerate() {
-            let x = {
-                if bg_tile_data {
-                    0x8000_u16.wrapping_add((*b as u16 * 16) as u16).wrapping_add((x_offset * 2) as u16)
+        let pixel: u16 = match obj {
+            Some(o) if obj_visible(&o) => {
+                if cgb_mode {
+                    bus.palette_ram.obj_color(o.cgb_palette, o.color)
                 } else {
-                    if *b <= 127 {
-                        0x9000_u16.wrapping_add(*b as u16 * 16).wrapping_add((x_offset * 2) as u16)
-                    } else {
-                        0x8800_u16.wrapping_add((*b - 128) as u16 * 16).wrapping_add((x_offset * 2) as u16)
-                    }
+                    let obp: u8 = bus.get_byte(if o.palette { OBP1 } else { OBP0 });
+                    let shade: u8 = obp >> (o.color * 2) & 0b11;
+                    // compatibility mode colours the shade with OBJ palette 0 or 1
+                    if self.model.is_cgb() { bus.palette_ram.obj_color(o.palette as u8, shade) } else { shade as u16 }
                 }
-            };
-            let result = self.combine_byte(bus.get_byte(x), bus.get_byte(x + 1u16), &palette);
-            for j in 0..result.len() {
-                pixels[i * 8 + j] = result[j];
             }
-        };
-        pixels
-    }
-
-    fn get_sprites(&mut self, bus: &Bus, ly: u8) -> [u8; 160] {
-        let lcdc = bus.get_byte(LCDC);
-        if lcdc >> 1 & 0x1 == 0 {
-            return [0; 160];
-        }
-
-        let obj1: u8 = bus.get_byte(0xFF48);
-        let mut palette_1: [u8; 4] = [0; 4];
-        for i in 0..4 {
-            palette_1[i] = obj1 >> i * 2 & 0b11;
-        }
-        let obj2: u8 = bus.get_byte(0xFF49);
-        let mut palette_2: [u8; 4] = [0; 4];
-        for i in 0..4 {
-            palette_2[i] = obj2 >> i * 2 & 0b11;
-        }
-
-        let mut res = [0; 160];
-        for i in 0..160 {
-            for entry in &self.oam_entries {
-                if entry.xpos < i + 0x8 && entry.xpos >= i {
-                    if entry.obj16 {
-                        let render: [u8; 128] = entry.render16;
-
-                        let l: u8 = ly - entry.ypos;
-
-                        println!("L {:?} YPOS {:?} LY: {:?}", l, entry.ypos, ly);
-                        for j in 0..8 {
-                            let x = (entry.xpos - 8 + j) as usize;
-                            let u8 = render[(l * 8 + j) as usize] as usize;
-                            res[x] = if entry.palette {
-                                palette_2[u8]
-                            } else {
-                                palette_1[u8]
-                            };
-                        }
-                    } else {
-                        let render: [u8; 64] = entry.render8;
-                        let l: u8 = entry.ypos - ly;
-
-                        for j in 0..8 {
-                            let x = (entry.xpos - 8 + j) as usize;
-                            let r = (l * 8 + j) as usize;
-                            let u8 = render[r] as usize;
-                            res[x] = if entry.palette {
-                                palette_2[u8]
-                            } else {
-                                palette_1[u8]
-                            };
-                        }
-                    }
+            _ => {
+                if cgb_mode {
+                    bus.palette_ram.bg_color(bg.palette, bg_color)
+                } else {
+                    let shade: u8 = bus.get_byte(BGP) >> (bg_color * 2) & 0b11;
+                    if self.model.is_cgb() { bus.palette_ram.bg_color(0, shade) } else { shade as u16 }
                 }
             }
-        }
-        self.oam_entries.clear();
-        res
-    }
-
-    #[inline]
-    fn combine_byte(&self, low: u8, top: u8, palette: &[u8; 4]) -> [u8; 8] {
-        let mut result: [u8; 8] = [0; 8];
-        for i in (0..8).rev() {
-            result[7 - i] = palette[((top >> i & 0x1) << 1 | (low >> i & 0x1)) as usize];
         };
-        result
-    }
-
-    fn do_vblank(&mut self, bus: &mut Bus) -> i32 {
-        let line: u8 = self.get_line(bus);
-        bus.set_byte(IF, bus.get_byte(IF) | 1);
-        bus.io_registers.ly = line + 1;
-        if line == 154 {
-            bus.io_registers.ly = 0;
-            self.set_video_mode(VideoMode::OamSearch, bus);
-            self.write_image(bus);
+        if !self.skip_frame {
+            self.image[ly as usize * 160 + self.lx as usize] = pixel;
         }
-        0
-    }
+        self.lx += 1;
 
-    fn do_hblank(&self, bus: &mut Bus) -> i32 {
-        let line = self.get_line(bus);
-        if line == 143 {
-            // last drawing line
-            // mode1 interrupt enabled
-            if bus.get_byte(STAT) >> 4 & 0x1 == 1 {
-                bus.set_byte(IF, bus.get_byte(IF) | 0b10);
-            }
-            bus.set_byte(IF, bus.get_byte(IF) | 0b1);
-            self.set_video_mode(VideoMode::VBLANK, bus);
-        } else {
-            // next line
-            // mode2 interrupt enabled
-            if bus.get_byte(STAT) >> 5 & 0x1 == 1 {
-                bus.set_byte(IF, bus.get_byte(IF) | 0b10);
+        if self.lx == 160 {
+            if self.fetcher.window {
+                self.window_line = self.window_line.wrapping_add(1);
             }
-            self.set_video_mode(VideoMode::OamSearch, bus);
+            self.oam_entries.clear();
+            self.set_video_mode(VideoMode::HBLANK, bus);
+            bus.hblank();
         }
-        bus.io_registers.ly = line + 1;
-        0
     }
 
-
-    // move to BUS
-    fn get_video_mode(&self, bus: &mut Bus) -> VideoMode {
-        match bus.get_byte(STAT) & 0x3 {
-            0 => VideoMode::HBLANK,
-            1 => VideoMode::VBLANK,
-            2 => VideoMode::OamSearch,
-            3 => VideoMode::PixelTransfer,
-            _ => panic!("")
-        }
+    // colour indices of the sprite row on the current line, left to right
+    fn fetch_obj_row(&self, bus: &Bus, entry: &OamEntry) -> [u8; 8] {
+        let address: u16 = entry.row_address(self.line);
+        let bank: u8 = if Ppu::cgb_mode(bus) && entry.bank { 1 } else { 0 };
+        tile_row(bus, bank, address, entry.xflip)
     }
 
-    fn get_line(&self, bus: &Bus) -> u8 {
-        bus.get_byte(LY)
+    // whether the PPU runs with CGB features rather than as a DMG, which CGB
+    // hardware does in compatibility mode too
+    pub fn cgb_mode(bus: &Bus) -> bool {
+        bus.model.is_cgb() && !bus.dmg_compat
     }
 
-    fn set_video_mode(&self, video_mode: VideoMode, bus: &mut Bus) {
+    fn set_video_mode(&mut self, video_mode: VideoMode, bus: &mut Bus) {
+        self.mode = video_mode;
         bus.io_registers.stat = (bus.get_byte(STAT) & 0b11111100) | video_mode as u8;
     }
 
@@ -511,3 +463,92 @@ impl Ppu {
     }
 }
 
+// the palette and colour correction are display settings and stay as they are
+impl SaveState for Ppu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.words(&self.image);
+        w.bool(self.ready);
+        w.u64(self.frames);
+        w.u8(self.oam_entries.len() as u8);
+        for entry in self.oam_entries.iter() {
+            entry.save_state(w);
+        }
+        w.u8(self.mode as u8);
+        w.u8(self.line);
+        w.u16(self.dot);
+        w.u8(self.lx);
+        w.u8(self.discard);
+        self.fetcher.save_state(w);
+        w.u8(self.bg_fifo.len() as u8);
+        for pixel in self.bg_fifo.iter() {
+            pixel.save_state(w);
+        }
+        w.u8(self.obj_fifo.len() as u8);
+        for pixel in self.obj_fifo.iter() {
+            pixel.save_state(w);
+        }
+        w.bool(self.obj_fetch.is_some());
+        if let Some(entry) = &self.obj_fetch {
+            entry.save_state(w);
+        }
+        w.u8(self.obj_fetch_ticks);
+        w.u8(self.window_line);
+        w.bool(self.wy_triggered);
+        w.bool(self.window_wraps);
+        w.bool(self.stat_line);
+        w.bool(self.lcd_on);
+        w.bool(self.skip_frame);
+        w.u32(self.off_dots);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        r.words_into(&mut self.image)?;
+        self.ready = r.bool()?;
+        self.frames = r.u64()?;
+        self.oam_entries.clear();
+        for _ in 0..r.u8()? {
+            let mut entry: OamEntry = OamEntry::new(0, [0; 4], false);
+            entry.load_state(r)?;
+            self.oam_entries.push(entry);
+        }
+        self.mode = match r.u8()? {
+            0 => VideoMode::HBLANK,
+            1 => VideoMode::VBLANK,
+            2 => VideoMode::OamSearch,
+            3 => VideoMode::PixelTransfer,
+            m => return Err(format!("invalid PPU mode {}", m)),
+        };
+        self.line = r.u8()?;
+        self.dot = r.u16()?;
+        self.lx = r.u8()?;
+        self.discard = r.u8()?;
+        self.fetcher.load_state(r)?;
+        self.bg_fifo.clear();
+        for _ in 0..r.u8()? {
+            let mut pixel: BgPixel = BgPixel { color: 0, palette: 0, priority: false };
+            pixel.load_state(r)?;
+            self.bg_fifo.push_back(pixel);
+        }
+        self.obj_fifo.clear();
+        for _ in 0..r.u8()? {
+            let mut pixel: ObjPixel = Default::default();
+            pixel.load_state(r)?;
+            self.obj_fifo.push_back(pixel);
+        }
+        self.obj_fetch = None;
+        if r.bool()? {
+            let mut entry: OamEntry = OamEntry::new(0, [0; 4], false);
+            entry.load_state(r)?;
+            self.obj_fetch = Some(entry);
+        }
+        self.obj_fetch_ticks = r.u8()?;
+        self.window_line = r.u8()?;
+        self.wy_triggered = r.bool()?;
+        self.window_wraps = r.bool()?;
+        self.stat_line = r.bool()?;
+        self.lcd_on = r.bool()?;
+        self.skip_frame = r.bool()?;
+        self.off_dots = r.u32()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,452 @@
+use crate::mmu::joypad::Joypad;
+use crate::ppu::palette::ColorCorrection;
+use crate::state::{SaveState, StateReader, StateWriter};
+
+static PAL01: u8 = 0x00;
+static PAL23: u8 = 0x01;
+static PAL03: u8 = 0x02;
+static PAL12: u8 = 0x03;
+static ATTR_BLK: u8 = 0x04;
+static ATTR_LIN: u8 = 0x05;
+static ATTR_DIV: u8 = 0x06;
+static ATTR_CHR: u8 = 0x07;
+static PAL_SET: u8 = 0x0A;
+static PAL_TRN: u8 = 0x0B;
+static MLT_REQ: u8 = 0x11;
+static CHR_TRN: u8 = 0x13;
+static PCT_TRN: u8 = 0x14;
+static ATTR_TRN: u8 = 0x15;
+static ATTR_SET: u8 = 0x16;
+static MASK_EN: u8 = 0x17;
+
+// bordered output frame, the Game Boy screen sitting in the middle
+pub static WIDTH: usize = 256;
+pub static HEIGHT: usize = 224;
+static SCREEN_X: usize = 48;
+static SCREEN_Y: usize = 40;
+
+// what a *_TRN command reads from the next frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Transfer {
+    // 512 system palettes for PAL_SET
+    Palettes,
+    // border tiles 0x00 - 0x7F, or 0x80 - 0xFF
+    Tiles(bool),
+    // border map and palettes 4-7
+    Border,
+    // 45 attribute files for ATTR_SET
+    Attributes,
+}
+
+// MASK_EN: how the screen is hidden while the game prepares the next one
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mask {
+    None,
+    // keeps the frame shown when the mask was set
+    Freeze,
+    Black,
+    // backdrop colour
+    Color0,
+}
+
+// Super Game Boy side of the hardware: command packets from the joypad register
+// colourize the 4 shades per 8x8 screen area and draw a border around the screen.
+// Data bigger than a packet (*_TRN) is shown on screen by the game, 4KB as the
+// first 256 tiles of the 20x18 screen, and read from the following frame.
+pub struct Sgb {
+    // RGB555 colours of the 4 screen palettes, colour 0 being shared
+    pub palettes: [[u16; 4]; 4],
+    system_palettes: Vec<[u16; 4]>,
+    // palette of every 8x8 area of the screen, 20 per row
+    pub attributes: [u8; 360],
+    pub mask: Mask,
+    frozen: Vec<u16>,
+    // 256 SNES 4bpp tiles of 32 bytes
+    border_tiles: Vec<u8>,
+    // 32x28 entries: tile in bits 0-7, palette 4-7 in bits 10-12, X flip bit 14, Y flip bit 15
+    border_map: Vec<u16>,
+    border_palettes: [[u16; 16]; 4],
+    // 45 files of 90 bytes, the palettes of the 360 areas at 2 bits each
+    attribute_files: Vec<u8>,
+    // packets of the command being received
+    command: Vec<u8>,
+    // transfer waiting for a frame after the given one
+    transfer: Option<(Transfer, u64)>,
+    // last command that isn't emulated, for debugging. Not part of save states.
+    pub unsupported: Option<u8>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Sgb::new()
+    }
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        Sgb {
+            palettes: [[0x7FFF, 0x56B5, 0x294A, 0x0000]; 4],
+            system_palettes: vec![[0; 4]; 512],
+            attributes: [0; 360],
+            mask: Mask::None,
+            frozen: vec![0; 160 * 144],
+            border_tiles: vec![0; 256 * 32],
+            border_map: vec![0; 32 * 28],
+            border_palettes: [[0; 16]; 4],
+            attribute_files: vec![0; 45 * 90],
+            command: Vec::new(),
+            transfer: None,
+            unsupported: None,
+        }
+    }
+
+    // handles the packets the joypad received and a transfer once `frames` frames
+    // are done, `image` holding the shades of the last frame
+    pub fn tick(&mut self, p1: &mut Joypad, frames: u64, image: &[u16]) {
+        while let Some(packet) = p1.packets.pop_front() {
+            self.packet(p1, packet, frames, image);
+        }
+        if let Some((transfer, after)) = self.transfer {
+            if frames > after {
+                self.transfer = None;
+                self.read_transfer(transfer, image);
+            }
+        }
+    }
+
+    fn packet(&mut self, p1: &mut Joypad, packet: [u8; 16], frames: u64, image: &[u16]) {
+        if self.command.is_empty() && packet[0] & 0x7 == 0 {
+            // a command is 1 to 7 packets long
+            return;
+        }
+        self.command.extend_from_slice(&packet);
+        if self.command.len() < (self.command[0] & 0x7) as usize * 16 {
+            return;
+        }
+        let command: Vec<u8> = std::mem::take(&mut self.command);
+        let data: &[u8] = &command[1..];
+        match command[0] >> 3 {
+            c if c == PAL01 => self.set_palettes(0, 1, data),
+            c if c == PAL23 => self.set_palettes(2, 3, data),
+            c if c == PAL03 => self.set_palettes(0, 3, data),
+            c if c == PAL12 => self.set_palettes(1, 2, data),
+            c if c == ATTR_BLK => self.attr_blk(data),
+            c if c == ATTR_LIN => self.attr_lin(data),
+            c if c == ATTR_DIV => self.attr_div(data),
+            c if c == ATTR_CHR => self.attr_chr(data),
+            c if c == PAL_SET => self.pal_set(data),
+            c if c == PAL_TRN => self.transfer = Some((Transfer::Palettes, frames)),
+            c if c == CHR_TRN => self.transfer = Some((Transfer::Tiles(data[0] & 0x1 == 1), frames)),
+            c if c == PCT_TRN => self.transfer = Some((Transfer::Border, frames)),
+            c if c == ATTR_TRN => self.transfer = Some((Transfer::Attributes, frames)),
+            c if c == ATTR_SET => self.attr_set(data),
+            c if c == MLT_REQ => p1.set_players(match data[0] & 0x3 {
+                1 => 2,
+                3 => 4,
+                _ => 1,
+            }),
+            c if c == MASK_EN => {
+                self.mask = match data[0] & 0x3 {
+                    0 => Mask::None,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                };
+                if self.mask == Mask::Freeze {
+                    self.frozen.copy_from_slice(image);
+                }
+            }
+            c => self.unsupported = Some(c),
+        }
+    }
+
+    // PALxx: shared colour 0 then colours 1-3 of both palettes
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |i: usize| data[i * 2] as u16 | (data[i * 2 + 1] as u16) << 8;
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    // sets of 6 bytes: which areas change, their palettes and the rectangle X1, Y1, X2, Y2
+    fn attr_blk(&mut self, data: &[u8]) {
+        let count: usize = (data[0] & 0x1F) as usize;
+        for set in data[1..].chunks(6).take(count).filter(|s| s.len() == 6) {
+            let mut control: u8 = set[0] & 0x7;
+            // changing only the inside or the outside changes the border with it
+            let mut palettes: [u8; 3] = [set[1] & 0x3, set[1] >> 2 & 0x3, set[1] >> 4 & 0x3];
+            if control == 0b001 {
+                control |= 0b010;
+                palettes[1] = palettes[0];
+            } else if control == 0b100 {
+                control |= 0b010;
+                palettes[1] = palettes[2];
+            }
+            let (x1, y1, x2, y2) = (set[2] & 0x1F, set[3] & 0x1F, set[4] & 0x1F, set[5] & 0x1F);
+            for y in 0..18 {
+                for x in 0..20 {
+                    let area: usize = if x > x1 && x < x2 && y > y1 && y < y2 {
+                        0
+                    } else if x >= x1 && x <= x2 && y >= y1 && y <= y2 {
+                        1
+                    } else {
+                        2
+                    };
+                    if control >> area & 0x1 == 1 {
+                        self.attributes[y as usize * 20 + x as usize] = palettes[area];
+                    }
+                }
+            }
+        }
+    }
+
+    // lines of one byte: number in bits 0-4, palette in bits 5-6, bit 7 set for a row
+    fn attr_lin(&mut self, data: &[u8]) {
+        let count: usize = data[0] as usize;
+        for line in data[1..].iter().take(count) {
+            let number: usize = (line & 0x1F) as usize;
+            let palette: u8 = line >> 5 & 0x3;
+            if line & 0x80 != 0 {
+                if number < 18 {
+                    self.attributes[number * 20..number * 20 + 20].fill(palette);
+                }
+            } else if number < 20 {
+                for y in 0..18 {
+                    self.attributes[y * 20 + number] = palette;
+                }
+            }
+        }
+    }
+
+    // splits the screen at a column (or a row with bit 6): palettes after, before
+    // and on the line in bits 0-1, 2-3 and 4-5
+    fn attr_div(&mut self, data: &[u8]) {
+        let (after, before, on) = (data[0] & 0x3, data[0] >> 2 & 0x3, data[0] >> 4 & 0x3);
+        let rows: bool = data[0] & 0x40 != 0;
+        let split: u8 = data[1] & 0x1F;
+        for y in 0..18_u8 {
+            for x in 0..20_u8 {
+                let position: u8 = if rows { y } else { x };
+                self.attributes[y as usize * 20 + x as usize] = match position.cmp(&split) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on,
+                    std::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    // palettes of consecutive areas from X, Y, 4 per byte from the high bits, going
+    // right (or down with direction 1)
+    fn attr_chr(&mut self, data: &[u8]) {
+        let (mut x, mut y) = ((data[0] % 20) as usize, (data[1] % 18) as usize);
+        let count: usize = (data[2] as usize | (data[3] as usize) << 8).min(360);
+        let down: bool = data[4] & 0x1 == 1;
+        for i in 0..count {
+            let Some(byte) = data.get(5 + i / 4) else { break };
+            self.attributes[y * 20 + x] = byte >> (6 - (i % 4) * 2) & 0x3;
+            if down {
+                y += 1;
+                if y == 18 {
+                    y = 0;
+                    x = (x + 1) % 20;
+                }
+            } else {
+                x += 1;
+                if x == 20 {
+                    x = 0;
+                    y = (y + 1) % 18;
+                }
+            }
+        }
+    }
+
+    // the 4 palettes from the system palettes, bit 6 of the last byte lifts the mask
+    fn pal_set(&mut self, data: &[u8]) {
+        for i in 0..4 {
+            let id: usize = (data[i * 2] as usize | (data[i * 2 + 1] as usize) << 8) & 0x1FF;
+            self.palettes[i] = self.system_palettes[id];
+        }
+        let color0: u16 = self.palettes[0][0];
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color0;
+        }
+        if data[8] & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    // the attributes from attribute file in bits 0-5, bit 6 lifts the mask
+    fn attr_set(&mut self, data: &[u8]) {
+        let file: usize = (data[0] & 0x3F) as usize;
+        if file < 45 {
+            for (i, attribute) in self.attributes.iter_mut().enumerate() {
+                *attribute = self.attribute_files[file * 90 + i / 4] >> (6 - (i % 4) * 2) & 0x3;
+            }
+        }
+        if data[0] & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    fn read_transfer(&mut self, transfer: Transfer, image: &[u16]) {
+        let data: Vec<u8> = Sgb::vram_data(image);
+        let word = |i: usize| data[i] as u16 | (data[i + 1] as u16) << 8;
+        match transfer {
+            Transfer::Palettes => {
+                for (i, palette) in self.system_palettes.iter_mut().enumerate() {
+                    for (j, color) in palette.iter_mut().enumerate() {
+                        *color = word(i * 8 + j * 2);
+                    }
+                }
+            }
+            Transfer::Tiles(high) => {
+                let start: usize = if high { 128 * 32 } else { 0 };
+                self.border_tiles[start..start + 4096].copy_from_slice(&data);
+            }
+            Transfer::Border => {
+                for (i, entry) in self.border_map.iter_mut().enumerate() {
+                    *entry = word(i * 2);
+                }
+                for (i, palette) in self.border_palettes.iter_mut().enumerate() {
+                    for (j, color) in palette.iter_mut().enumerate() {
+                        *color = word(0x800 + i * 32 + j * 2);
+                    }
+                }
+            }
+            Transfer::Attributes => self.attribute_files.copy_from_slice(&data[..45 * 90]),
+        }
+    }
+
+    // the 4KB shown as the first 256 tiles of the screen, 2 bytes per tile row
+    fn vram_data(image: &[u16]) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![0; 4096];
+        for tile in 0..256 {
+            for row in 0..8 {
+                let y: usize = tile / 20 * 8 + row;
+                for i in 0..8 {
+                    let shade: u16 = image[y * 160 + tile % 20 * 8 + i];
+                    data[tile * 16 + row * 2] |= ((shade & 0x1) as u8) << (7 - i);
+                    data[tile * 16 + row * 2 + 1] |= ((shade >> 1 & 0x1) as u8) << (7 - i);
+                }
+            }
+        }
+        data
+    }
+
+    // RGB pixels of the 256x224 output with the colourized screen and the border
+    pub fn frame(&self, image: &[u16]) -> Vec<u8> {
+        let backdrop: u16 = self.palettes[0][0];
+        let mut frame: Vec<u16> = vec![backdrop; WIDTH * HEIGHT];
+        for y in 0..144 {
+            for x in 0..160 {
+                let shade: usize = (image[y * 160 + x] & 0x3) as usize;
+                let palette: usize = self.attributes[y / 8 * 20 + x / 8] as usize;
+                frame[(SCREEN_Y + y) * WIDTH + SCREEN_X + x] = match self.mask {
+                    Mask::None => self.palettes[palette][shade],
+                    Mask::Freeze => self.palettes[palette][(self.frozen[y * 160 + x] & 0x3) as usize],
+                    Mask::Black => 0,
+                    Mask::Color0 => backdrop,
+                };
+            }
+        }
+        // the border goes over the screen, colour 0 being transparent
+        for (i, entry) in self.border_map.iter().enumerate() {
+            let tile: usize = (entry & 0xFF) as usize;
+            let palette: usize = (entry >> 10 & 0x7) as usize % 4;
+            for row in 0..8 {
+                let line: usize = if entry & 0x8000 != 0 { 7 - row } else { row };
+                let planes: [u8; 4] = [
+                    self.border_tiles[tile * 32 + line * 2],
+                    self.border_tiles[tile * 32 + line * 2 + 1],
+                    self.border_tiles[tile * 32 + 16 + line * 2],
+                    self.border_tiles[tile * 32 + 16 + line * 2 + 1],
+                ];
+                for column in 0..8 {
+                    let bit: usize = if entry & 0x4000 != 0 { column } else { 7 - column };
+                    let color: usize = planes.iter().enumerate().map(|(p, b)| ((b >> bit & 0x1) as usize) << p).sum();
+                    if color != 0 {
+                        frame[(i / 32 * 8 + row) * WIDTH + i % 32 * 8 + column] = self.border_palettes[palette][color];
+                    }
+                }
+            }
+        }
+        frame.iter().flat_map(|c| ColorCorrection::None.rgb(*c)).collect()
+    }
+}
+
+impl SaveState for Sgb {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.words(&self.palettes.concat());
+        w.words(&self.system_palettes.concat());
+        w.bytes(&self.attributes);
+        w.u8(match self.mask {
+            Mask::None => 0,
+            Mask::Freeze => 1,
+            Mask::Black => 2,
+            Mask::Color0 => 3,
+        });
+        w.words(&self.frozen);
+        w.bytes(&self.border_tiles);
+        w.words(&self.border_map);
+        w.words(&self.border_palettes.concat());
+        w.bytes(&self.attribute_files);
+        w.bytes(&self.command);
+        // none, palettes, low tiles, high tiles, border or attributes, and the frame it waits for
+        let (transfer, after): (u8, u64) = match self.transfer {
+            None => (0, 0),
+            Some((Transfer::Palettes, after)) => (1, after),
+            Some((Transfer::Tiles(false), after)) => (2, after),
+            Some((Transfer::Tiles(true), after)) => (3, after),
+            Some((Transfer::Border, after)) => (4, after),
+            Some((Transfer::Attributes, after)) => (5, after),
+        };
+        w.u8(transfer);
+        w.u64(after);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let mut colors: Vec<u16> = vec![0; 16];
+        r.words_into(&mut colors)?;
+        for (palette, chunk) in self.palettes.iter_mut().zip(colors.chunks(4)) {
+            palette.copy_from_slice(chunk);
+        }
+        let mut colors: Vec<u16> = vec![0; 512 * 4];
+        r.words_into(&mut colors)?;
+        for (palette, chunk) in self.system_palettes.iter_mut().zip(colors.chunks(4)) {
+            palette.copy_from_slice(chunk);
+        }
+        r.bytes_into(&mut self.attributes)?;
+        self.mask = match r.u8()? {
+            0 => Mask::None,
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            _ => Mask::Color0,
+        };
+        r.words_into(&mut self.frozen)?;
+        r.bytes_into(&mut self.border_tiles)?;
+        r.words_into(&mut self.border_map)?;
+        let mut colors: Vec<u16> = vec![0; 4 * 16];
+        r.words_into(&mut colors)?;
+        for (palette, chunk) in self.border_palettes.iter_mut().zip(colors.chunks(16)) {
+            palette.copy_from_slice(chunk);
+        }
+        r.bytes_into(&mut self.attribute_files)?;
+        self.command = r.bytes()?;
+        let transfer: u8 = r.u8()?;
+        let after: u64 = r.u64()?;
+        self.transfer = match transfer {
+            1 => Some((Transfer::Palettes, after)),
+            2 => Some((Transfer::Tiles(false), after)),
+            3 => Some((Transfer::Tiles(true), after)),
+            4 => Some((Transfer::Border, after)),
+            5 => Some((Transfer::Attributes, after)),
+            _ => None,
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+// Save states: a header identifying the format and the ROM, then every component
+// writes its fields in a fixed order. Integers are little endian, byte and word
+// buffers are prefixed with their u32 length.
+//
+// Header:
+//   "GRST" magic, u16 version, u32 CRC-32 of the ROM, u8 model
+//
+// Bump VERSION whenever a component changes what it writes.
+pub static MAGIC: &[u8; 4] = b"GRST";
+pub static VERSION: u16 = 2;
+
+// a component that can be written to and restored from a save state
+pub trait SaveState {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), String>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    pub data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn words(&mut self, words: &[u16]) {
+        self.u32(words.len() as u32);
+        for word in words {
+            self.u16(*word);
+        }
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end: usize = self.position + length;
+        if end > self.data.len() {
+            return Err(format!("save state ends after {} bytes, expected more data", self.data.len()));
+        }
+        let slice: &'a [u8] = &self.data[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        let b: &[u8] = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        let b: &[u8] = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        let mut b: [u8; 8] = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let length: usize = self.u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    // a buffer that has to fill `buffer` exactly
+    pub fn bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), String> {
+        let length: usize = self.u32()? as usize;
+        if length != buffer.len() {
+            return Err(format!("save state has {} bytes where {} were expected", length, buffer.len()));
+        }
+        buffer.copy_from_slice(self.take(length)?);
+        Ok(())
+    }
+
+    pub fn words_into(&mut self, buffer: &mut [u16]) -> Result<(), String> {
+        let length: usize = self.u32()? as usize;
+        if length != buffer.len() {
+            return Err(format!("save state has {} words where {} were expected", length, buffer.len()));
+        }
+        for word in buffer.iter_mut() {
+            *word = self.u16()?;
+        }
+        Ok(())
+    }
+
+    // whether everything was read
+    pub fn finished(&self) -> bool {
+        self.position == self.data.len()
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// DMG with 0x55 at the start of VRAM and 0x66 at the start of OAM, written while
+// the boot ROM hands over during VBlank
+fn access_machine(rom: &[u8]) -> Gameboy {
+    let mut gb = common::start(rom, Model::Dmg);
+    gb.bus.set_byte(0x8000, 0x55);
+    gb.bus.set_byte(0xFE00, 0x66);
+    assert_eq!(gb.bus.peek(0x8000), 0x55);
+    assert_eq!(gb.bus.peek(0xFE00), 0x66);
+    gb
+}
+
+fn mode(gb: &Gameboy) -> u8 {
+    gb.bus.io_registers.stat & 0b11
+}
+
+// runs the PPU to mode `m` on line 10, or to VBlank
+fn run_to_mode(gb: &mut Gameboy, m: u8) {
+    while !(mode(gb) == m && (m == 1 || gb.bus.io_registers.ly == 10)) {
+        gb.ppu.tick(&mut gb.bus, 1);
+    }
+}
+
+// VRAM reads 0xFF and ignores writes in mode 3, OAM in modes 2 and 3. Both are
+// open again in HBlank and VBlank.
+#[test]
+fn locked_by_mode() {
+    // (mode, VRAM open, OAM open)
+    let modes: [(u8, bool, bool); 4] = [(2, true, false), (3, false, false), (0, true, true), (1, true, true)];
+    for (m, vram, oam) in modes.iter() {
+        let mut gb = access_machine(&common::rom());
+        run_to_mode(&mut gb, *m);
+        assert_eq!(gb.bus.get_byte(0x8000), if *vram { 0x55 } else { 0xFF }, "VRAM read in mode {}", m);
+        assert_eq!(gb.bus.get_byte(0xFE00), if *oam { 0x66 } else { 0xFF }, "OAM read in mode {}", m);
+        gb.bus.set_byte(0x8000, 0x11);
+        gb.bus.set_byte(0xFE00, 0x22);
+        assert_eq!(gb.bus.peek(0x8000), if *vram { 0x11 } else { 0x55 }, "VRAM write in mode {}", m);
+        assert_eq!(gb.bus.peek(0xFE00), if *oam { 0x22 } else { 0x66 }, "OAM write in mode {}", m);
+    }
+}
+
+// With the LCD off STAT reads mode 0 and both are open.
+#[test]
+fn open_with_lcd_off() {
+    let mut gb = access_machine(&common::rom());
+    run_to_mode(&mut gb, 3);
+    gb.bus.set_byte(0xFF40, 0x00);
+    gb.tick();
+    assert_eq!(mode(&gb), 0);
+    assert_eq!(gb.bus.get_byte(0x8000), 0x55);
+    assert_eq!(gb.bus.get_byte(0xFE00), 0x66);
+}
+
+// The same seen by a program: it waits for mode 3, reads and writes both and
+// keeps what it read at 0xC000, then writes VRAM again in VBlank.
+#[test]
+fn cpu_in_mode_3() {
+    let program: &[u8] = &[
+        0x21, 0x00, 0x80,       // ld hl, $8000
+        0x11, 0x00, 0xFE,       // ld de, $FE00
+        0xF0, 0x41,             // .m3: ldh a, [$FF41]
+        0xE6, 0x03,             //      and a, $03
+        0xFE, 0x03,             //      cp a, $03
+        0x20, 0xF8,             //      jr nz, .m3
+        0x46,                   //      ld b, [hl]
+        0x1A,                   //      ld a, [de]
+        0x4F,                   //      ld c, a
+        0x3E, 0x11,             //      ld a, $11
+        0xEA, 0x01, 0x80,       //      ld [$8001], a
+        0xEA, 0x01, 0xFE,       //      ld [$FE01], a
+        0x78,                   //      ld a, b
+        0xEA, 0x00, 0xC0,       //      ld [$C000], a
+        0x79,                   //      ld a, c
+        0xEA, 0x01, 0xC0,       //      ld [$C001], a
+        0xF0, 0x41,             // .m1: ldh a, [$FF41]
+        0xE6, 0x03,             //      and a, $03
+        0xFE, 0x01,             //      cp a, $01
+        0x20, 0xF8,             //      jr nz, .m1
+        0xEA, 0x02, 0x80,       //      ld [$8002], a
+        0x18, 0xFE,             // .done: jr .done
+    ];
+    let mut rom: Vec<u8> = common::rom();
+    rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+    rom[0x0150..0x0150 + program.len()].copy_from_slice(&program);
+    let mut gb = access_machine(&rom);
+    common::run_frames(&mut gb, 2);
+
+    assert_eq!(gb.bus.get_byte(0xC000), 0xFF, "VRAM read in mode 3");
+    assert_eq!(gb.bus.get_byte(0xC001), 0xFF, "OAM read in mode 3");
+    assert_eq!(gb.bus.peek(0x8001), 0x00, "VRAM write in mode 3");
+    assert_eq!(gb.bus.peek(0xFE01), 0x00, "OAM write in mode 3");
+    assert_eq!(gb.bus.peek(0x8002), 0x01, "VRAM write in VBlank");
+}
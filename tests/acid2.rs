@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use gamerust::golden::{GoldenCase, Outcome};
+
+// The acid2 ROMs and their reference images are not redistributed here. Put
+// dmg-acid2.gb, cgb-acid2.gbc and their reference-dmg.png / reference-cgb.png
+// from https://github.com/mattcurrie/dmg-acid2 and cgb-acid2 in one directory and
+// run
+//   ACID2_ROMS=<directory> cargo test --test acid2 -- --ignored
+// A missing variable or file fails the test rather than skipping it.
+fn acid2(line: &str) {
+    let dir: String = std::env::var("ACID2_ROMS").expect("ACID2_ROMS must name the directory holding the acid2 ROMs");
+    let case = GoldenCase::parse(line, Path::new(&dir)).unwrap_or_else(|e| panic!("{}", e));
+    for file in [&case.rom, &case.reference].iter() {
+        assert!(Path::new(file).exists(), "{}: {} not found", case.name, file);
+    }
+    match case.run(&format!("{}/target/golden", env!("CARGO_MANIFEST_DIR")), false) {
+        Ok(Outcome::Passed) => {}
+        Ok(Outcome::Failed { mismatches, actual, diff }) => {
+            panic!("{}: {} pixels differ, see {} and {}", case.name, mismatches, actual, diff)
+        }
+        Ok(Outcome::Skipped) => panic!("{}: {} not found", case.name, case.rom),
+        Ok(Outcome::Blessed) => unreachable!(),
+        Err(e) => panic!("{}: {}", case.name, e),
+    }
+}
+
+#[test]
+#[ignore = "needs ACID2_ROMS"]
+fn dmg_acid2() {
+    acid2("dmg-acid2 dmg-acid2.gb 60 reference-dmg.png model=dmg");
+}
+
+#[test]
+#[ignore = "needs ACID2_ROMS"]
+fn cgb_acid2() {
+    acid2("cgb-acid2 cgb-acid2.gbc 60 reference-cgb.png model=cgb");
+}
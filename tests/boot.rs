@@ -0,0 +1,50 @@
+mod common;
+
+use gamerust::cpu::registers::RR;
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+use gamerust::options::Options;
+
+// A CGB boot ROM image that only writes 0x11 to FF50 at its very end, like the
+// real one, so execution falls through to the cartridge at 0x0100.
+#[test]
+fn cgb_boot_rom_unmaps_on_0x11() {
+    let dir: &str = env!("CARGO_TARGET_TMPDIR");
+    let mut bootrom: Vec<u8> = vec![0x00; 0x900];
+    bootrom[0x00FC..0x0100].copy_from_slice(&[0x3E, 0x11, 0xE0, 0x50]);
+    bootrom[0x0200] = 0xAA;
+    let bootrom_path: String = format!("{}/cgb_boot.bin", dir);
+    std::fs::write(&bootrom_path, &bootrom).unwrap();
+
+    let mut rom: Vec<u8> = common::cgb_rom();
+    rom[0x0000] = 0x55;
+    rom[0x0200] = 0x66;
+
+    let options = Options {
+        model: Some(Model::Cgb),
+        bootrom: Some(bootrom_path),
+        ..Default::default()
+    };
+    let mut gb = Gameboy::from_rom_bytes(&rom, &options);
+    assert!(gb.bus.boot_rom());
+    assert_eq!(gb.bus.get_byte(0x0200), 0xAA);
+
+    for _ in 0..1000 {
+        gb.tick();
+    }
+    assert!(!gb.bus.boot_rom(), "boot ROM still mapped after FF50 = 0x11");
+    assert_eq!(gb.bus.get_byte(0x0000), 0x55);
+    assert_eq!(gb.bus.get_byte(0x0200), 0x66);
+    assert_eq!(gb.cpu.registers.get_r16(RR::PC), 0x0100);
+}
+
+// the DMG boot ROM leaves H and C set unless the header checksum is 0
+#[test]
+fn dmg_flags_follow_the_header_checksum() {
+    let mut rom: Vec<u8> = common::rom();
+    rom[0x014D] = 0x00;
+    assert_eq!(common::start(&rom, Model::Dmg).cpu.registers.get_r16(RR::AF), 0x0180);
+    rom[0x014D] = 0xE7;
+    assert_eq!(common::start(&rom, Model::Dmg).cpu.registers.get_r16(RR::AF), 0x01B0);
+    assert_eq!(common::start(&rom, Model::Mgb).cpu.registers.get_r16(RR::AF), 0xFFB0);
+}
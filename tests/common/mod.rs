@@ -0,0 +1,38 @@
+// Helpers shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+use gamerust::options::Options;
+
+// 32 KiB ROM-only cartridge that loops forever at 0x0100, for tests to patch
+pub fn rom() -> Vec<u8> {
+    let mut rom: Vec<u8> = vec![0x00; 0x8000];
+    rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+    rom
+}
+
+// the same ROM flagged as a CGB-only cartridge
+pub fn cgb_rom() -> Vec<u8> {
+    let mut rom: Vec<u8> = rom();
+    rom[0x0143] = 0x80;
+    rom
+}
+
+// CGB running cgb_rom from the post-boot state
+pub fn cgb() -> Gameboy {
+    start(&cgb_rom(), Model::Cgb)
+}
+
+// runs `rom` as `model` from the post-boot state
+pub fn start(rom: &[u8], model: Model) -> Gameboy {
+    let options = Options { model: Some(model), skip_boot: true, ..Default::default() };
+    Gameboy::from_rom_bytes(rom, &options)
+}
+
+pub fn run_frames(gb: &mut Gameboy, frames: u64) {
+    let end: u64 = gb.ppu.frames + frames;
+    while gb.ppu.frames < end {
+        gb.tick();
+    }
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+use gamerust::options::Options;
+
+// Boots a DMG cartridge with `title` and `licensee` on CGB without the boot ROM,
+// optionally holding a combo, and returns BG palette 0 and OBJ palettes 0 and 1.
+fn palettes(title: &str, licensee: u8, combo: Option<&str>) -> [[u16; 4]; 3] {
+    let mut rom: Vec<u8> = common::rom();
+    rom[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+    rom[0x014B] = licensee;
+
+    let options = Options {
+        model: Some(Model::Cgb),
+        skip_boot: true,
+        compat_palette: combo.map(String::from),
+        ..Default::default()
+    };
+    let gb = Gameboy::from_rom_bytes(&rom, &options);
+    let colors = |f: &dyn Fn(u8) -> u16| -> [u16; 4] { [f(0), f(1), f(2), f(3)] };
+    [
+        colors(&|c| gb.bus.palette_ram.bg_color(0, c)),
+        colors(&|c| gb.bus.palette_ram.obj_color(0, c)),
+        colors(&|c| gb.bus.palette_ram.obj_color(1, c)),
+    ]
+}
+
+static RED: [u16; 4] = [0x7FFF, 0x421F, 0x1CF2, 0x0000];
+static LIGHT_GREEN: [u16; 4] = [0x7FFF, 0x1BEF, 0x0200, 0x0000];
+static BLUE: [u16; 4] = [0x7FFF, 0x7E8C, 0x7C00, 0x0000];
+static DARK_GREEN: [u16; 4] = [0x7FFF, 0x1BEF, 0x6180, 0x0000];
+static ORANGE: [u16; 4] = [0x7FFF, 0x03FF, 0x001F, 0x0000];
+
+// title checksum 0xDB, combination 3
+#[test]
+fn checksum_lookup() {
+    assert_eq!(palettes("TETRIS", 0x01, None), [ORANGE, ORANGE, ORANGE]);
+    // combination 13
+    assert_eq!(palettes("POKEMON RED", 0x01, None), [RED, LIGHT_GREEN, RED]);
+}
+
+#[test]
+fn other_licensees_get_the_default() {
+    assert_eq!(palettes("TETRIS", 0x33, None), [DARK_GREEN, RED, RED]);
+    assert_eq!(palettes("UNKNOWN TITLE", 0x01, None), [DARK_GREEN, RED, RED]);
+}
+
+// POKEMON BLUE and VEGAS STAKES both sum to 0x61
+#[test]
+fn fourth_letter_disambiguation() {
+    // combination 11
+    assert_eq!(palettes("POKEMON BLUE", 0x01, None), [BLUE, RED, BLUE]);
+    // combination 41
+    assert_eq!(palettes("VEGAS STAKES", 0x01, None), [LIGHT_GREEN, RED, BLUE]);
+    // same checksum, a fourth letter the table doesn't list
+    assert_eq!(palettes("POEKMON BLUE", 0x01, None), [DARK_GREEN, RED, RED]);
+}
+
+// a held combo wins over the title, up+a is combination 43
+#[test]
+fn combo_overrides_the_title() {
+    assert_eq!(palettes("POKEMON BLUE", 0x01, Some("up+a")), [RED, LIGHT_GREEN, BLUE]);
+}
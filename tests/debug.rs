@@ -0,0 +1,57 @@
+mod common;
+
+use gamerust::ppu::debug;
+
+// On CGB the tile map view colours tiles with the BG palette picked by their attributes.
+#[test]
+fn cgb_tile_map_uses_palette_ram() {
+    let mut gb = common::cgb();
+    gb.bus.access_checks = false;
+    // colour 0 of BG palette 2 is pure red, the first map entry uses palette 2
+    gb.bus.set_byte(0xFF68, 0x80 | 2 * 8);
+    gb.bus.set_byte(0xFF69, 0x1F);
+    gb.bus.set_byte(0xFF69, 0x00);
+    gb.bus.set_byte(0xFF4F, 1);
+    gb.bus.set_byte(0x9800, 0x02);
+    gb.bus.set_byte(0xFF4F, 0);
+
+    let view = debug::tile_map(&gb.bus, &gb.ppu.palette, gb.ppu.color_correction, 0);
+    // away from the outline of the screen area at the top left
+    assert_eq!(view.get_pixel(4, 4), [255, 0, 0]);
+    assert_eq!(view.get_pixel(12, 4), [255, 255, 255]);
+}
+
+// On CGB sprites take their colours from the OBJ palette in attribute bits 0-2.
+#[test]
+fn cgb_oam_sheet_uses_palette_ram() {
+    let mut gb = common::cgb();
+    gb.bus.access_checks = false;
+    // colour 3 of OBJ palette 5 is pure green, tile 1 is solid colour 3
+    gb.bus.set_byte(0xFF6A, 0x80 | (5 * 8 + 3 * 2));
+    gb.bus.set_byte(0xFF6B, 0xE0);
+    gb.bus.set_byte(0xFF6B, 0x03);
+    for i in 0..16 {
+        gb.bus.set_byte(0x8010 + i, 0xFF);
+    }
+    gb.bus.set_byte(0xFE02, 0x01);
+    gb.bus.set_byte(0xFE03, 0x05);
+
+    let view = debug::oam_sheet(&gb.bus, &gb.ppu.palette, gb.ppu.color_correction);
+    assert_eq!(view.get_pixel(4, 4), [0, 255, 0]);
+    let entry = &debug::sprites(&gb.bus)[0];
+    assert_eq!(debug::sprite_color(&gb.bus, entry, 3, &gb.ppu.palette, gb.ppu.color_correction), [0, 255, 0]);
+}
+
+// The tile sheet shows CGB tiles through BG palette 0, like the screen with the default attributes.
+#[test]
+fn cgb_tiles_use_palette_ram() {
+    let mut gb = common::cgb();
+    gb.bus.access_checks = false;
+    // colour 0 of BG palette 0 is pure blue
+    gb.bus.set_byte(0xFF68, 0x80);
+    gb.bus.set_byte(0xFF69, 0x00);
+    gb.bus.set_byte(0xFF69, 0x7C);
+
+    let view = debug::tiles(&gb.bus, &gb.ppu.palette, gb.ppu.color_correction);
+    assert_eq!(view.get_pixel(4, 4), [0, 0, 255]);
+}
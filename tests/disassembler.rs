@@ -0,0 +1,88 @@
+mod common;
+
+use gamerust::cpu::disassembler::{Instruction, RomBank};
+use gamerust::model::Model;
+
+// decodes `bytes` placed at `address` in an otherwise empty address space
+fn decode(address: u16, bytes: &[u8]) -> Instruction {
+    let mut mem: Vec<u8> = vec![0; 0x10000];
+    for (i, b) in bytes.iter().enumerate() {
+        mem[(address as usize + i) & 0xFFFF] = *b;
+    }
+    Instruction::decode(&mem[..], address)
+}
+
+fn check(cases: &[(u16, &[u8], &str)]) {
+    for (address, bytes, expected) in cases {
+        let instruction = decode(*address, bytes);
+        assert_eq!(instruction.to_string(), *expected, "{:02X?} at ${:04X}", bytes, address);
+        assert_eq!(instruction.size() as usize, bytes.len(), "size of {}", expected);
+    }
+}
+
+#[test]
+fn cb_prefixed() {
+    check(&[
+        (0x0100, &[0xCB, 0x00], "rlc b"),
+        (0x0100, &[0xCB, 0x1F], "rr a"),
+        (0x0100, &[0xCB, 0x37], "swap a"),
+        (0x0100, &[0xCB, 0x3E], "srl [hl]"),
+        (0x0100, &[0xCB, 0x7E], "bit 7, [hl]"),
+        (0x0100, &[0xCB, 0x86], "res 0, [hl]"),
+        (0x0100, &[0xCB, 0xC7], "set 0, a"),
+        (0x0100, &[0xCB, 0xFF], "set 7, a"),
+    ]);
+}
+
+#[test]
+fn ldh() {
+    check(&[
+        (0x0100, &[0xE0, 0x44], "ldh [$FF44], a"),
+        (0x0100, &[0xF0, 0x44], "ldh a, [$FF44]"),
+        (0x0100, &[0xE2], "ldh [c], a"),
+        (0x0100, &[0xF2], "ldh a, [c]"),
+    ]);
+}
+
+#[test]
+fn jr_targets() {
+    check(&[
+        (0x0100, &[0x18, 0xFE], "jr $0100"),
+        (0x0100, &[0x18, 0x00], "jr $0102"),
+        (0x0100, &[0x20, 0x05], "jr nz, $0107"),
+        (0x0100, &[0x38, 0x80], "jr c, $0082"),
+        // wraps around the address space
+        (0xFFFE, &[0x18, 0x7F], "jr $007F"),
+    ]);
+}
+
+#[test]
+fn illegal_opcodes() {
+    for opcode in [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD] {
+        check(&[(0x0100, &[opcode], &format!("db ${:02X}", opcode))]);
+    }
+}
+
+// the bus shows the disassembler the ROM even while OAM DMA keeps the CPU off it
+#[test]
+fn bus_during_oam_dma() {
+    let mut rom: Vec<u8> = common::rom();
+    rom[0x0200..0x0203].copy_from_slice(&[0xC3, 0x50, 0x01]);
+    let mut gb = common::start(&rom, Model::Dmg);
+    gb.bus.set_byte(0xFF46, 0xC0);
+    gb.bus.tick_dma();
+    gb.bus.tick_dma();
+    assert_eq!(gb.bus.get_byte(0x0200), 0xFF);
+    assert_eq!(Instruction::decode(&gb.bus, 0x0200).to_string(), "jp $0150");
+}
+
+// an instruction at the end of bank 0 takes its operands from the bank above
+#[test]
+fn across_bank_0_end() {
+    let mut rom: Vec<u8> = vec![0x00; 0x10000];
+    rom[0x3FFF] = 0xC3;
+    rom[0x4000..0x4002].copy_from_slice(&[0x34, 0x12]);
+    rom[0x8000..0x8002].copy_from_slice(&[0x78, 0x56]);
+    assert_eq!(Instruction::decode(&RomBank::new(&rom, 0), 0x3FFF).to_string(), "jp $1234");
+    assert_eq!(Instruction::decode(&RomBank::new(&rom, 2), 0x3FFF).to_string(), "jp $5678");
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// DMG with pages 0xC0 and 0xC1 of WRAM holding i and 0x80 + i
+fn dma_machine(rom: &[u8]) -> Gameboy {
+    let mut gb = common::start(rom, Model::Dmg);
+    for i in 0..0xA0_u16 {
+        gb.bus.set_byte(0xC000 + i, i as u8);
+        gb.bus.set_byte(0xC100 + i, (0x80 + i) as u8);
+    }
+    gb
+}
+
+fn oam(gb: &Gameboy) -> Vec<u8> {
+    (0xFE00..0xFEA0).map(|address| gb.bus.peek(address)).collect()
+}
+
+// M-cycles until the transfer is over
+fn run_dma(gb: &mut Gameboy) -> u32 {
+    let mut cycles: u32 = 0;
+    while gb.bus.dma.active() || cycles == 0 {
+        gb.bus.tick_dma();
+        cycles += 1;
+    }
+    cycles
+}
+
+// One setup M-cycle, then a byte per M-cycle for 160 M-cycles.
+#[test]
+fn takes_160_cycles() {
+    let mut gb = dma_machine(&common::rom());
+    gb.bus.set_byte(0xFF46, 0xC0);
+    gb.bus.tick_dma();
+    assert!(gb.bus.dma.active());
+    assert_eq!(gb.bus.peek(0xFE00), 0x00);
+    for i in 0..0xA0_u16 {
+        gb.bus.tick_dma();
+        assert_eq!(gb.bus.peek(0xFE00 + i), i as u8, "after {} M-cycles", i + 2);
+        assert_eq!(gb.bus.dma.active(), i < 0x9F, "after {} M-cycles", i + 2);
+    }
+
+    let mut gb = dma_machine(&common::rom());
+    gb.bus.set_byte(0xFF46, 0xC1);
+    assert_eq!(run_dma(&mut gb), 161);
+    assert_eq!(oam(&gb), (0x80..0x120).map(|i: u16| i as u8).collect::<Vec<u8>>());
+}
+
+// Meanwhile only HRAM and the I/O registers answer, everything else reads 0xFF
+// and ignores writes.
+#[test]
+fn cpu_limited_to_hram() {
+    let mut gb = dma_machine(&common::rom());
+    gb.bus.set_byte(0xFF46, 0xC0);
+    gb.bus.tick_dma();
+    for address in [0x0100_u16, 0x4000, 0x8000, 0xA000, 0xC005, 0xE005, 0xFE00].iter() {
+        assert_eq!(gb.bus.get_byte(*address), 0xFF, "0x{:04X}", address);
+    }
+    gb.bus.set_byte(0xC100, 0x12);
+    gb.bus.set_byte(0xFF80, 0x34);
+    gb.bus.set_byte(0xFF47, 0x1B);
+    assert_eq!(gb.bus.get_byte(0xFF80), 0x34);
+    assert_eq!(gb.bus.get_byte(0xFF47), 0x1B);
+
+    run_dma(&mut gb);
+    assert_eq!(gb.bus.get_byte(0xC005), 0x05);
+    assert_eq!(gb.bus.get_byte(0xC100), 0x80, "write during the transfer was kept");
+}
+
+// The usual routine in HRAM: it starts the transfer, reads WRAM at once and again
+// after waiting 160 M-cycles, and keeps both reads in HRAM.
+#[test]
+fn cpu_waits_in_hram() {
+    let routine: &[u8] = &[
+        0x3E, 0xC0,             // ld a, $C0
+        0xE0, 0x46,             // ldh [$FF46], a
+        0xFA, 0x05, 0xC0,       // ld a, [$C005]
+        0xE0, 0xF0,             // ldh [$FFF0], a
+        0x3E, 0x28,             // ld a, $28
+        0x3D,                   // .wait: dec a
+        0x20, 0xFD,             //        jr nz, .wait
+        0xFA, 0x05, 0xC0,       // ld a, [$C005]
+        0xE0, 0xF1,             // ldh [$FFF1], a
+        0x18, 0xFE,             // .done: jr .done
+    ];
+    let mut rom: Vec<u8> = common::rom();
+    rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x80, 0xFF]);
+    let mut gb = dma_machine(&rom);
+    for (i, b) in routine.iter().enumerate() {
+        gb.bus.set_byte(0xFF80 + i as u16, *b);
+    }
+    common::run_frames(&mut gb, 1);
+
+    assert_eq!(gb.bus.get_byte(0xFFF0), 0xFF);
+    assert_eq!(gb.bus.get_byte(0xFFF1), 0x05);
+    assert_eq!(oam(&gb), (0..0xA0).collect::<Vec<u8>>());
+}
+
+// Writing 0xFF46 again restarts the transfer from the new page. The old one
+// copies one more byte during the setup cycle of the new one.
+#[test]
+fn restart() {
+    let mut gb = dma_machine(&common::rom());
+    gb.bus.set_byte(0xFF46, 0xC0);
+    for _ in 0..50 {
+        gb.bus.tick_dma();
+    }
+    assert_eq!(gb.bus.peek(0xFE30), 0x30);
+    assert_eq!(gb.bus.peek(0xFE31), 0x00);
+
+    gb.bus.set_byte(0xFF46, 0xC1);
+    gb.bus.tick_dma();
+    assert_eq!(gb.bus.peek(0xFE31), 0x31);
+    assert_eq!(gb.bus.peek(0xFE00), 0x00);
+    gb.bus.tick_dma();
+    assert_eq!(gb.bus.peek(0xFE00), 0x80);
+    assert_eq!(gb.bus.peek(0xFE32), 0x00);
+
+    assert_eq!(run_dma(&mut gb), 159);
+    assert_eq!(oam(&gb), (0x80..0x120).map(|i: u16| i as u8).collect::<Vec<u8>>());
+}
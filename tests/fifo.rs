@@ -0,0 +1,159 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// DMG with tile 1 solid colour 3 and a background of tiles 0 and 1 in turn, so
+// every line is 8 pixels of colour 0 then 8 of colour 3, from the top of a frame
+fn stripes() -> Gameboy {
+    let mut gb = common::start(&common::rom(), Model::Dmg);
+    gb.bus.access_checks = false;
+    for row in 0..8 {
+        gb.bus.set_byte(0x8010 + row * 2, 0xFF);
+        gb.bus.set_byte(0x8011 + row * 2, 0xFF);
+    }
+    for i in 0..0x400_u16 {
+        gb.bus.set_byte(0x9800 + i, (i % 2) as u8);
+    }
+    gb.bus.set_byte(0xFF47, 0xE4);
+    gb.bus.set_byte(0xFF40, 0x91);
+    common::run_frames(&mut gb, 1);
+    gb
+}
+
+// the PPU alone, one dot at a time
+fn dot(gb: &mut Gameboy) {
+    gb.ppu.tick(&mut gb.bus, 1);
+}
+
+fn mode(gb: &Gameboy) -> u8 {
+    gb.bus.io_registers.stat & 0b11
+}
+
+// runs until the first dot of line `ly`, in OAM search
+fn run_to_line(gb: &mut Gameboy, ly: u8) {
+    while !(gb.bus.io_registers.ly == ly && mode(gb) == 2) {
+        dot(gb);
+    }
+}
+
+// dots line `ly` spends in mode 3
+fn mode3_dots(gb: &mut Gameboy, ly: u8) -> u32 {
+    run_to_line(gb, ly);
+    let mut dots: u32 = 0;
+    while mode(gb) != 0 {
+        if mode(gb) == 3 {
+            dots += 1;
+        }
+        dot(gb);
+    }
+    dots
+}
+
+// writes `value` to `address` `dots` into mode 3 of line 10 and returns the
+// first pixel of that line that differs from line 9
+fn write_mid_line(address: u16, value: u8, dots: u32) -> Option<usize> {
+    let mut gb = stripes();
+    run_to_line(&mut gb, 10);
+    while mode(&gb) != 3 {
+        dot(&mut gb);
+    }
+    for _ in 0..dots {
+        dot(&mut gb);
+    }
+    gb.bus.set_byte(address, value);
+    common::run_frames(&mut gb, 1);
+    (0..160).find(|x| gb.ppu.image[10 * 160 + x] != gb.ppu.image[9 * 160 + x])
+}
+
+// Pixels leave the FIFO one per dot after the 12 dots of the first two tile
+// fetches, so a palette or LCDC write shows from the pixel drawn on that dot.
+#[test]
+fn bgp_write_mid_line() {
+    assert_eq!(write_mid_line(0xFF47, 0x1B, 20), Some(8));
+    assert_eq!(write_mid_line(0xFF47, 0x1B, 40), Some(28));
+    assert_eq!(write_mid_line(0xFF47, 0x1B, 100), Some(88));
+}
+
+#[test]
+fn lcdc_write_mid_line() {
+    // BG off
+    assert_eq!(write_mid_line(0xFF40, 0x90, 40), Some(28));
+    assert_eq!(write_mid_line(0xFF40, 0x90, 100), Some(88));
+}
+
+// SCX is read again for every tile fetched, the tiles already in the FIFO or
+// being fetched keep the old value.
+#[test]
+fn scx_write_mid_line() {
+    assert_eq!(write_mid_line(0xFF43, 8, 20), Some(16));
+    assert_eq!(write_mid_line(0xFF43, 8, 40), Some(40));
+    assert_eq!(write_mid_line(0xFF43, 8, 100), Some(96));
+}
+
+// Mode 3 takes 172 dots plus the SCX % 8 pixels dropped at the start of the line.
+#[test]
+fn mode3_scx() {
+    for scx in 0..16 {
+        let mut gb = stripes();
+        gb.bus.set_byte(0xFF43, scx);
+        assert_eq!(mode3_dots(&mut gb, 10), 172 + scx as u32 % 8, "SCX = {}", scx);
+    }
+}
+
+// Every sprite adds 6 dots, plus the wait for the background fetcher: 5 dots less
+// one for each pixel of the background tile it starts after, if any. Sprites at
+// X = 0 are fetched at the first pixel and take 11 dots.
+#[test]
+fn mode3_sprites() {
+    // (OAM X, SCX, dots)
+    let cases: [(u8, u8, u32); 8] = [
+        (8, 0, 183),
+        (9, 0, 182),
+        (12, 0, 179),
+        (13, 0, 178),
+        (15, 0, 178),
+        (13, 3, 186),
+        (18, 3, 181),
+        (0, 0, 183),
+    ];
+    for (x, scx, dots) in cases.iter() {
+        let mut gb = stripes();
+        gb.bus.set_byte(0xFF43, *scx);
+        gb.bus.set_byte(0xFE00, 26);
+        gb.bus.set_byte(0xFE01, *x);
+        gb.bus.set_byte(0xFF40, 0x93);
+        assert_eq!(mode3_dots(&mut gb, 10), *dots, "X = {}, SCX = {}", x, scx);
+    }
+
+    // the second sprite starts 6 pixels into its background tile
+    let mut gb = stripes();
+    for (i, x) in [50_u8, 70].iter().enumerate() {
+        gb.bus.set_byte(0xFE00 + i as u16 * 4, 26);
+        gb.bus.set_byte(0xFE01 + i as u16 * 4, *x);
+    }
+    gb.bus.set_byte(0xFF40, 0x93);
+    assert_eq!(mode3_dots(&mut gb, 10), 172 + 9 + 6);
+
+    // sprites on other lines cost nothing
+    assert_eq!(mode3_dots(&mut gb, 30), 172);
+}
+
+// Starting the window fetch costs 6 dots.
+#[test]
+fn mode3_window() {
+    for wx in [50_u8, 100, 166].iter() {
+        let mut gb = stripes();
+        gb.bus.set_byte(0xFF4A, 0);
+        gb.bus.set_byte(0xFF4B, *wx);
+        gb.bus.set_byte(0xFF40, 0xB1);
+        assert_eq!(mode3_dots(&mut gb, 10), 178, "WX = {}", wx);
+    }
+
+    // below WY the line is not longer
+    let mut gb = stripes();
+    gb.bus.set_byte(0xFF4A, 20);
+    gb.bus.set_byte(0xFF4B, 50);
+    gb.bus.set_byte(0xFF40, 0xB1);
+    assert_eq!(mode3_dots(&mut gb, 10), 172);
+}
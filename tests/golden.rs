@@ -0,0 +1,25 @@
+use gamerust::golden::{GoldenCase, Outcome};
+
+// Runs every case of tests/golden/golden.txt, a missing ROM fails its case. Set
+// GOLDEN_BLESS=1 to write the reference of cases that do not have one yet.
+#[test]
+fn golden_frames() {
+    let dir: &str = env!("CARGO_MANIFEST_DIR");
+    let cases = GoldenCase::load_manifest(&format!("{}/tests/golden/golden.txt", dir)).unwrap_or_else(|e| panic!("{}", e));
+    let out_dir: String = format!("{}/target/golden", dir);
+    let bless: bool = std::env::var("GOLDEN_BLESS").map_or(false, |v| v == "1");
+
+    let mut failures: Vec<String> = Vec::new();
+    for case in cases {
+        match case.run(&out_dir, bless) {
+            Ok(Outcome::Passed) => println!("{}: ok", case.name),
+            Ok(Outcome::Skipped) => failures.push(format!("{}: {} not found", case.name, case.rom)),
+            Ok(Outcome::Blessed) => println!("{}: reference written to {}", case.name, case.reference),
+            Ok(Outcome::Failed { mismatches, actual, diff }) => {
+                failures.push(format!("{}: {} pixels differ, see {} and {}", case.name, mismatches, actual, diff))
+            }
+            Err(e) => failures.push(format!("{}: {}", case.name, e)),
+        }
+    }
+    assert!(failures.is_empty(), "golden frames failed:\n{}", failures.join("\n"));
+}
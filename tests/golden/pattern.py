@@ -0,0 +1,148 @@
+#!/usr/bin/env python3
+# Builds pattern.gb and draws its reference frame, pattern.png, from a model of
+# what the program puts on screen. The reference is computed here rather than
+# captured from the emulator, so the golden test checks the PPU against it.
+#
+#   python3 tests/golden/pattern.py
+#
+# rewrites both files next to this script.
+
+import os
+import struct
+import zlib
+
+# SM83 program at 0x0150, the header jumps here
+PROGRAM = [
+    # wait for VBlank and turn the LCD off
+    0xF0, 0x44,              # .wait: ldh a, [$FF44]
+    0xFE, 0x90,              #        cp a, $90
+    0x20, 0xFA,              #        jr nz, .wait
+    0xAF,                    #        xor a, a
+    0xE0, 0x40,              #        ldh [$FF40], a
+    # tiles 1 - 4 from 0x0200
+    0x21, 0x10, 0x80,        #        ld hl, $8010
+    0x11, 0x00, 0x02,        #        ld de, $0200
+    0x0E, 0x40,              #        ld c, $40
+    0x1A,                    # .copy: ld a, [de]
+    0x22,                    #        ld [hl+], a
+    0x13,                    #        inc de
+    0x0D,                    #        dec c
+    0x20, 0xFA,              #        jr nz, .copy
+    # map 0x9800: tiles 1, 2, 3, 4 repeating
+    0x21, 0x00, 0x98,        #        ld hl, $9800
+    0x7D,                    # .bg:   ld a, l
+    0xE6, 0x03,              #        and a, $03
+    0x3C,                    #        inc a
+    0x22,                    #        ld [hl+], a
+    0x7C,                    #        ld a, h
+    0xFE, 0x9C,              #        cp a, $9C
+    0x20, 0xF6,              #        jr nz, .bg
+    # map 0x9C00: tile 4 everywhere
+    0x3E, 0x04,              # .win:  ld a, $04
+    0x22,                    #        ld [hl+], a
+    0x7C,                    #        ld a, h
+    0xFE, 0xA0,              #        cp a, $A0
+    0x20, 0xF8,              #        jr nz, .win
+    # object 0: tile 3 at (32, 24), object 1: tile 2 at (72, 44) flipped both ways
+    0x21, 0x00, 0xFE,        #        ld hl, $FE00
+    0x36, 0x28, 0x23,        #        ld [hl], $28 / inc hl
+    0x36, 0x28, 0x23,        #        ld [hl], $28 / inc hl
+    0x36, 0x03, 0x23,        #        ld [hl], $03 / inc hl
+    0x36, 0x00, 0x23,        #        ld [hl], $00 / inc hl
+    0x36, 0x3C, 0x23,        #        ld [hl], $3C / inc hl
+    0x36, 0x50, 0x23,        #        ld [hl], $50 / inc hl
+    0x36, 0x02, 0x23,        #        ld [hl], $02 / inc hl
+    0x36, 0x60, 0x23,        #        ld [hl], $60 / inc hl
+    # identity BGP and OBP0, SCY = 5, SCX = 3, window at WY = 0x60, WX = 0x57
+    0x3E, 0xE4, 0xE0, 0x47,  #        ld a, $E4 / ldh [$FF47], a
+    0x3E, 0xE4, 0xE0, 0x48,  #        ld a, $E4 / ldh [$FF48], a
+    0x3E, 0x05, 0xE0, 0x42,  #        ld a, $05 / ldh [$FF42], a
+    0x3E, 0x03, 0xE0, 0x43,  #        ld a, $03 / ldh [$FF43], a
+    0x3E, 0x60, 0xE0, 0x4A,  #        ld a, $60 / ldh [$FF4A], a
+    0x3E, 0x57, 0xE0, 0x4B,  #        ld a, $57 / ldh [$FF4B], a
+    # LCD on, window map 0x9C00, window on, tile data 0x8000, BG map 0x9800,
+    # 8x8 objects on, BG on
+    0x3E, 0xF3, 0xE0, 0x40,  #        ld a, $F3 / ldh [$FF40], a
+    0x18, 0xFE,              # .done: jr .done
+]
+
+# 2bpp tile data, low byte then high byte of every row
+TILES = [
+    # 1: colours 1 and 2 alternating
+    [0xAA, 0x55] * 8,
+    # 2: colour 3 diagonal from the top left
+    [b for row in range(8) for b in (0x80 >> row, 0x80 >> row)],
+    # 3: solid colour 3
+    [0xFF, 0xFF] * 8,
+    # 4: rows of colour 2 and colour 0
+    [0x00, 0xFF, 0x00, 0x00] * 4,
+]
+
+SCX, SCY = 3, 5
+WX, WY = 0x57, 0x60
+# (Y, X, tile, attributes) as written to OAM
+OBJECTS = [(0x28, 0x28, 3, 0x00), (0x3C, 0x50, 2, 0x60)]
+SHADES = [255, 170, 85, 0]
+
+
+def rom():
+    data = bytearray(0x8000)
+    data[0x0100:0x0104] = bytes([0x00, 0xC3, 0x50, 0x01])
+    data[0x0134:0x013B] = b"PATTERN"
+    checksum = 0
+    for b in data[0x0134:0x014D]:
+        checksum = (checksum - b - 1) & 0xFF
+    data[0x014D] = checksum
+    data[0x0150:0x0150 + len(PROGRAM)] = bytes(PROGRAM)
+    tiles = bytes(b for tile in TILES for b in tile)
+    data[0x0200:0x0200 + len(tiles)] = tiles
+    return bytes(data)
+
+
+def tile_color(tile, row, column):
+    low, high = TILES[tile - 1][row * 2], TILES[tile - 1][row * 2 + 1]
+    bit = 7 - column
+    return (high >> bit & 1) << 1 | (low >> bit & 1)
+
+
+def color(x, y):
+    if y >= WY and x >= WX - 7:
+        # map 0x9C00 holds tile 4 only, the window starts at its first line
+        return tile_color(4, (y - WY) % 8, (x - WX + 7) % 8)
+    for oy, ox, tile, attributes in OBJECTS:
+        row, column = y - (oy - 16), x - (ox - 8)
+        if 0 <= row < 8 and 0 <= column < 8:
+            if attributes & 0x40:
+                row = 7 - row
+            if attributes & 0x20:
+                column = 7 - column
+            c = tile_color(tile, row, column)
+            if c != 0:
+                return c
+    bx, by = (x + SCX) % 256, (y + SCY) % 256
+    # map entry n holds tile (n & 3) + 1 and rows are 32 entries long
+    return tile_color(bx // 8 % 4 + 1, by % 8, bx % 8)
+
+
+def png(width, height, rgb):
+    def chunk(kind, data):
+        body = kind + data
+        return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
+    raw = b"".join(b"\x00" + rgb[y * width * 3:(y + 1) * width * 3] for y in range(height))
+    return (b"\x89PNG\r\n\x1a\n"
+            + chunk(b"IHDR", struct.pack(">IIBBBBB", width, height, 8, 2, 0, 0, 0))
+            + chunk(b"IDAT", zlib.compress(raw, 9))
+            + chunk(b"IEND", b""))
+
+
+def main():
+    here = os.path.dirname(os.path.abspath(__file__))
+    with open(os.path.join(here, "pattern.gb"), "wb") as f:
+        f.write(rom())
+    rgb = bytes(SHADES[color(x, y)] for y in range(144) for x in range(160) for _ in range(3))
+    with open(os.path.join(here, "pattern.png"), "wb") as f:
+        f.write(png(160, 144, rgb))
+
+
+if __name__ == "__main__":
+    main()
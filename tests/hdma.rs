@@ -0,0 +1,101 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+
+// CGB machine with 0x80 bytes counting up from 0 at 0xC000
+fn cgb() -> Gameboy {
+    let mut gb = common::cgb();
+    for i in 0..0x80_u16 {
+        gb.bus.set_byte(0xC000 + i, i as u8 + 1);
+    }
+    gb
+}
+
+// points HDMA at 0xC000 -> 0x8000 and writes HDMA5
+fn start(gb: &mut Gameboy, hdma5: u8) {
+    gb.bus.set_byte(0xFF51, 0xC0);
+    gb.bus.set_byte(0xFF52, 0x00);
+    gb.bus.set_byte(0xFF53, 0x00);
+    gb.bus.set_byte(0xFF54, 0x00);
+    gb.bus.set_byte(0xFF55, hdma5);
+}
+
+// blocks of 16 bytes copied to 0x8000 so far
+fn copied(gb: &Gameboy) -> u16 {
+    (0..0x80_u16).take_while(|i| gb.bus.peek(0x8000 + i) == *i as u8 + 1).count() as u16 / 0x10
+}
+
+fn run_to_mode(gb: &mut Gameboy, mode: u8) {
+    while gb.bus.io_registers.stat & 0b11 != mode {
+        gb.tick();
+    }
+}
+
+#[test]
+fn general_purpose_copies_everything() {
+    let mut gb = cgb();
+    gb.bus.set_byte(0xFF40, 0x00);
+    start(&mut gb, 0x03);
+    assert_eq!(copied(&gb), 4);
+    assert_eq!(gb.bus.get_byte(0xFF55), 0xFF);
+    // 8 M-cycles per block
+    assert_eq!(gb.bus.hdma.stall, 32);
+}
+
+// with the LCD off one block is copied on the HDMA5 write, then nothing until HBlanks come back
+#[test]
+fn hblank_with_lcd_off() {
+    let mut gb = cgb();
+    gb.bus.set_byte(0xFF40, 0x00);
+    start(&mut gb, 0x83);
+    assert_eq!(copied(&gb), 1);
+    assert_eq!(gb.bus.get_byte(0xFF55), 0x02);
+    for _ in 0..1000 {
+        gb.tick();
+    }
+    assert_eq!(copied(&gb), 1);
+}
+
+// started during HBlank the first block doesn't wait for the next one
+#[test]
+fn hblank_started_in_hblank() {
+    let mut gb = cgb();
+    run_to_mode(&mut gb, 3);
+    run_to_mode(&mut gb, 0);
+    start(&mut gb, 0x83);
+    assert_eq!(copied(&gb), 1);
+    run_to_mode(&mut gb, 3);
+    run_to_mode(&mut gb, 0);
+    assert_eq!(copied(&gb), 2);
+}
+
+// started outside HBlank it copies one block per HBlank, HDMA5 counting down
+#[test]
+fn hblank_copies_a_block_per_line() {
+    let mut gb = cgb();
+    run_to_mode(&mut gb, 3);
+    start(&mut gb, 0x81);
+    assert_eq!(copied(&gb), 0);
+    assert_eq!(gb.bus.get_byte(0xFF55), 0x01);
+    run_to_mode(&mut gb, 0);
+    assert_eq!(copied(&gb), 1);
+    assert_eq!(gb.bus.get_byte(0xFF55), 0x00);
+    run_to_mode(&mut gb, 3);
+    run_to_mode(&mut gb, 0);
+    assert_eq!(copied(&gb), 2);
+    assert_eq!(gb.bus.get_byte(0xFF55), 0xFF);
+}
+
+// writing HDMA5 with bit 7 clear stops a HBlank DMA, bit 7 then reads 1
+#[test]
+fn hblank_cancel() {
+    let mut gb = cgb();
+    run_to_mode(&mut gb, 3);
+    start(&mut gb, 0x83);
+    run_to_mode(&mut gb, 0);
+    gb.bus.set_byte(0xFF55, 0x00);
+    assert_eq!(gb.bus.get_byte(0xFF55), 0x82);
+    run_to_mode(&mut gb, 3);
+    run_to_mode(&mut gb, 0);
+    assert_eq!(copied(&gb), 1);
+}
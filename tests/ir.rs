@@ -0,0 +1,108 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::ir_link::IrLink;
+use gamerust::model::Model;
+
+// loop iterations of the sender between lighting the LED and turning it off
+static LOOPS: u8 = 50;
+
+// CGB that lights its LED once for a fixed time: RP = 1, a dec b / jr nz loop, RP = 0
+fn sender() -> Gameboy {
+    let mut rom: Vec<u8> = common::cgb_rom();
+    rom[0x0100..0x010F].copy_from_slice(&[
+        0x3E, 0x01,     // ld a, $01
+        0xE0, 0x56,     // ldh [$FF56], a
+        0x06, LOOPS,    // ld b, LOOPS
+        0x05,           // .loop: dec b
+        0x20, 0xFD,     // jr nz, .loop
+        0xAF,           // xor a, a
+        0xE0, 0x56,     // ldh [$FF56], a
+        0x18, 0xFE,     // jr @
+        0x00,
+    ]);
+    common::start(&rom, Model::Cgb)
+}
+
+// CGB looping forever with the IR receiver enabled
+fn receiver() -> Gameboy {
+    let mut gb = common::cgb();
+    gb.bus.set_byte(0xFF56, 0xC0);
+    gb
+}
+
+// dots the sender LED stays on and dots the receiver reads the signal, each
+// measured on its own clock
+fn pulse(link: &mut IrLink) -> (u64, u64) {
+    let (mut led_on, mut led_off): (Option<u64>, Option<u64>) = (None, None);
+    let (mut seen_on, mut seen_off): (Option<u64>, Option<u64>) = (None, None);
+    while seen_off.is_none() || led_off.is_none() {
+        assert!(link.a.time < 100_000, "no pulse went through");
+        link.tick();
+        let led: bool = link.a.bus.infrared.led();
+        if led && led_on.is_none() {
+            led_on = Some(link.a.time);
+        } else if !led && led_on.is_some() && led_off.is_none() {
+            led_off = Some(link.a.time);
+        }
+        let signal: bool = link.b.bus.get_byte(0xFF56) & 0x2 == 0;
+        if signal && seen_on.is_none() {
+            seen_on = Some(link.b.time);
+        } else if !signal && seen_on.is_some() && seen_off.is_none() {
+            seen_off = Some(link.b.time);
+        }
+    }
+    (led_off.unwrap() - led_on.unwrap(), seen_off.unwrap() - seen_on.unwrap())
+}
+
+// between the two RP writes: ldh, ld b, the loop less the last jr not taken, xor
+static PULSE_M_CYCLES: u64 = 3 + 2 + LOOPS as u64 * 4 - 1 + 1;
+
+#[test]
+fn pulse_length_at_normal_speed() {
+    let mut link = IrLink::new(sender(), receiver());
+    let (sent, seen) = pulse(&mut link);
+    assert_eq!(sent, PULSE_M_CYCLES * 4);
+    assert!(sent.abs_diff(seen) <= 4, "sent {} dots, seen for {}", sent, seen);
+}
+
+// a double speed sender pulses for half the dots, the receiver sees just that
+#[test]
+fn pulse_length_from_double_speed() {
+    let mut a: Gameboy = sender();
+    a.bus.double_speed = true;
+    let mut link = IrLink::new(a, receiver());
+    let (sent, seen) = pulse(&mut link);
+    assert_eq!(sent, PULSE_M_CYCLES * 2);
+    assert!(sent.abs_diff(seen) <= 4, "sent {} dots, seen for {}", sent, seen);
+}
+
+// and a double speed receiver sees a normal speed pulse at its full length
+#[test]
+fn pulse_length_to_double_speed() {
+    let mut b: Gameboy = receiver();
+    b.bus.double_speed = true;
+    let mut link = IrLink::new(sender(), b);
+    let (sent, seen) = pulse(&mut link);
+    assert_eq!(sent, PULSE_M_CYCLES * 4);
+    assert!(sent.abs_diff(seen) <= 2, "sent {} dots, seen for {}", sent, seen);
+}
+
+// RP bit 1 reads 0 only with reading enabled (bits 6-7) and the other LED on
+#[test]
+fn rp_read_values() {
+    let mut link = IrLink::new(receiver(), receiver());
+    let mut read = |rp: u8, led: bool| -> u8 {
+        link.a.bus.set_byte(0xFF56, led as u8);
+        link.b.bus.set_byte(0xFF56, rp);
+        link.tick();
+        link.b.bus.get_byte(0xFF56)
+    };
+    assert_eq!(read(0x00, true), 0x3E);
+    assert_eq!(read(0x40, true), 0x7E);
+    assert_eq!(read(0x80, true), 0xBE);
+    assert_eq!(read(0xC0, false), 0xFE);
+    assert_eq!(read(0xC0, true), 0xFC);
+    // its own LED doesn't reach its receiver
+    assert_eq!(read(0xC1, false), 0xFF);
+}
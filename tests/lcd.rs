@@ -0,0 +1,92 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// DMG showing a dark screen: an empty background with BGP mapping colour 0 to
+// shade 3, from the top of a frame
+fn dark_machine() -> Gameboy {
+    let mut gb = common::start(&common::rom(), Model::Dmg);
+    gb.bus.set_byte(0xFF47, 0x03);
+    common::run_frames(&mut gb, 2);
+    assert!(gb.ppu.image.iter().all(|pixel| *pixel == 3));
+    gb
+}
+
+// M-cycles until the next frame is handed out
+fn frame_cycles(gb: &mut Gameboy) -> u32 {
+    let frames: u64 = gb.ppu.frames;
+    let mut cycles: u32 = 0;
+    while gb.ppu.frames == frames {
+        gb.tick();
+        cycles += 1;
+    }
+    cycles
+}
+
+// Turning the LCD off hands out a blank frame at once, then one every 17556
+// M-cycles as if it was still on.
+#[test]
+fn blank_frames_while_off() {
+    let mut gb = dark_machine();
+    let frames: u64 = gb.ppu.frames;
+    gb.bus.set_byte(0xFF40, 0x11);
+    gb.tick();
+    assert_eq!(gb.ppu.frames, frames + 1);
+    assert!(gb.ppu.ready);
+    assert!(gb.ppu.image.iter().all(|pixel| *pixel == 0), "screen not blank");
+    assert_eq!(gb.bus.get_byte(0xFF44), 0);
+    assert_eq!(gb.bus.get_byte(0xFF41) & 0b11, 0);
+
+    // the M-cycle that turned it off counts towards the first one
+    assert_eq!(frame_cycles(&mut gb), 17556 - 1);
+    for _ in 0..3 {
+        assert_eq!(frame_cycles(&mut gb), 17556);
+        assert!(gb.ppu.image.iter().all(|pixel| *pixel == 0));
+    }
+}
+
+// The first frame after turning the LCD on again is not shown, the screen stays
+// blank until the second one.
+#[test]
+fn first_frame_after_enable_skipped() {
+    let mut gb = dark_machine();
+    gb.bus.set_byte(0xFF40, 0x11);
+    common::run_frames(&mut gb, 2);
+    gb.bus.set_byte(0xFF40, 0x91);
+
+    // from line 0 to VBlank
+    assert_eq!(frame_cycles(&mut gb), 144 * 456 / 4);
+    assert!(gb.ppu.image.iter().all(|pixel| *pixel == 0), "first frame shown");
+    assert_eq!(frame_cycles(&mut gb), 17556);
+    assert!(gb.ppu.image.iter().all(|pixel| *pixel == 3), "second frame not shown");
+}
+
+// Line 0 after turning the LCD on has no OAM search: STAT reads mode 0 for those
+// 80 dots, OAM stays open and the mode 2 interrupt is not requested until line 1.
+#[test]
+fn no_oam_search_on_first_line() {
+    let mut gb = dark_machine();
+    gb.bus.set_byte(0xFE00, 0x66);
+    gb.bus.set_byte(0xFF40, 0x11);
+    gb.tick();
+    gb.bus.set_byte(0xFF41, 0x20);
+    gb.bus.set_byte(0xFF0F, 0);
+    gb.bus.set_byte(0xFF40, 0x91);
+
+    let mut modes: Vec<u8> = Vec::new();
+    while gb.bus.io_registers.ly == 0 {
+        gb.ppu.tick(&mut gb.bus, 1);
+        let mode: u8 = gb.bus.io_registers.stat & 0b11;
+        if modes.last() != Some(&mode) {
+            modes.push(mode);
+        }
+        if mode == 0 && modes.len() == 1 {
+            assert_eq!(gb.bus.get_byte(0xFE00), 0x66);
+        }
+        assert_eq!(gb.bus.get_byte(0xFF0F) & 0b10, 0, "mode 2 interrupt on line 0");
+    }
+    assert_eq!(modes, vec![0, 3, 0, 2]);
+    gb.ppu.tick(&mut gb.bus, 1);
+    assert_eq!(gb.bus.get_byte(0xFF0F) & 0b10, 0b10, "no mode 2 interrupt on line 1");
+}
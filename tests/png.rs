@@ -0,0 +1,37 @@
+use gamerust::png;
+
+// The images in tests/png were written by Python's zlib (1.2+), not by this
+// crate, so they check the decoder against an independent encoder:
+//   fixed.png    4x2 RGB, compressed with Z_FIXED, a single fixed Huffman block
+//   dynamic.png  16x16 RGB, compressed at level 9, a single dynamic Huffman block
+// Both use filter type 0 on every row.
+
+fn expected(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 3]) -> Vec<u8> {
+    (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).flat_map(|(x, y)| pixel(x, y).to_vec()).collect()
+}
+
+#[test]
+fn fixed_huffman() {
+    let data: &[u8] = include_bytes!("png/fixed.png");
+    // BTYPE is bits 1-2 of the first deflate byte, after the 2 byte zlib header
+    assert_eq!(data[43] >> 1 & 0b11, 1);
+    let (width, height, rgb) = png::decode(data).unwrap();
+    assert_eq!((width, height), (4, 2));
+    assert_eq!(rgb, expected(4, 2, |x, y| [(x * 64) as u8, (y * 128) as u8, (255 - x * 64) as u8]));
+}
+
+#[test]
+fn dynamic_huffman() {
+    let data: &[u8] = include_bytes!("png/dynamic.png");
+    assert_eq!(data[43] >> 1 & 0b11, 2);
+    let (width, height, rgb) = png::decode(data).unwrap();
+    assert_eq!((width, height), (16, 16));
+    assert_eq!(rgb, expected(16, 16, |x, y| [(x * 16) as u8, (y * 16) as u8, ((x ^ y) * 8) as u8]));
+}
+
+// the encoder's stored blocks read back through the same decoder
+#[test]
+fn round_trip() {
+    let rgb: Vec<u8> = expected(16, 16, |x, y| [(x * 16) as u8, (y * 16) as u8, ((x ^ y) * 8) as u8]);
+    assert_eq!(png::decode(&png::encode(16, 16, &rgb)).unwrap(), (16, 16, rgb));
+}
@@ -0,0 +1,325 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+use gamerust::ppu::palette::ColorCorrection;
+use gamerust::sgb::{self, Mask, Sgb};
+
+fn p1(gb: &mut Gameboy, value: u8) {
+    gb.bus.set_byte(0xFF00, value);
+}
+
+// a packet sent the way games do: a reset pulse, the 128 bits from bit 0 of the
+// first byte as P15 (1) or P14 (0) pulses and a 0 stop bit, every pulse followed
+// by both lines high
+fn send_packet(gb: &mut Gameboy, packet: &[u8]) {
+    p1(gb, 0x00);
+    p1(gb, 0x30);
+    for bit in 0..128 {
+        p1(gb, if packet[bit / 8] >> (bit % 8) & 0x1 == 1 { 0x10 } else { 0x20 });
+        p1(gb, 0x30);
+    }
+    p1(gb, 0x20);
+    p1(gb, 0x30);
+}
+
+// sends a command padded to its length in packets and lets the SGB handle it
+fn command(gb: &mut Gameboy, bytes: &[u8]) {
+    let mut data: Vec<u8> = bytes.to_vec();
+    data.resize((bytes[0] & 0x7) as usize * 16, 0);
+    for packet in data.chunks(16) {
+        send_packet(gb, packet);
+    }
+    gb.tick();
+}
+
+fn sgb(gb: &Gameboy) -> &Sgb {
+    gb.sgb.as_ref().unwrap()
+}
+
+// RGB of the 160x144 screen inside the bordered frame
+fn screen(gb: &Gameboy) -> Vec<[u8; 3]> {
+    let frame: Vec<u8> = sgb(gb).frame(&gb.ppu.image);
+    let mut pixels: Vec<[u8; 3]> = Vec::with_capacity(160 * 144);
+    for y in 0..144 {
+        for x in 0..160 {
+            let i: usize = ((40 + y) * sgb::WIDTH + 48 + x) * 3;
+            pixels.push([frame[i], frame[i + 1], frame[i + 2]]);
+        }
+    }
+    pixels
+}
+
+// 4 KiB of made up transfer data
+fn pattern() -> Vec<u8> {
+    (0..4096).map(|i: usize| (i * 37 + i / 256) as u8).collect()
+}
+
+// SGB showing `data` as the first 256 tiles of the screen, how games prepare a
+// *_TRN transfer
+fn transfer_machine(data: &[u8]) -> Gameboy {
+    let mut gb = common::start(&common::rom(), Model::Sgb);
+    gb.bus.set_byte(0xFF40, 0x00);
+    gb.tick();
+    for (i, b) in data.iter().enumerate() {
+        gb.bus.set_byte(0x8000 + i as u16, *b);
+    }
+    for tile in 0..360_u16 {
+        gb.bus.set_byte(0x9800 + tile / 20 * 32 + tile % 20, tile as u8);
+    }
+    gb.bus.set_byte(0xFF47, 0xE4);
+    gb.bus.set_byte(0xFF40, 0x91);
+    common::run_frames(&mut gb, 2);
+    gb
+}
+
+// expected attribute map, `palette` giving the palette of area X, Y
+fn attribute_map(palette: impl Fn(usize, usize) -> u8) -> Vec<u8> {
+    (0..360).map(|i| palette(i % 20, i / 20)).collect()
+}
+
+// Packets only start after a reset pulse and are handed over with their stop bit.
+// Another reset pulse starts the packet over.
+#[test]
+fn packet_transport() {
+    let packet: Vec<u8> = (0..16).map(|i: u16| (i * 17 + 1) as u8).collect();
+    let mut gb = common::start(&common::rom(), Model::Sgb);
+
+    for _ in 0..8 {
+        p1(&mut gb, 0x10);
+        p1(&mut gb, 0x30);
+    }
+    assert!(gb.bus.io_registers.p1.packets.is_empty(), "bits without a reset pulse");
+
+    p1(&mut gb, 0x00);
+    p1(&mut gb, 0x30);
+    for _ in 0..40 {
+        p1(&mut gb, 0x10);
+        p1(&mut gb, 0x30);
+    }
+    send_packet(&mut gb, &packet);
+    assert_eq!(gb.bus.io_registers.p1.packets, vec![[0x01, 0x12, 0x23, 0x34, 0x45, 0x56, 0x67, 0x78,
+        0x89, 0x9A, 0xAB, 0xBC, 0xCD, 0xDE, 0xEF, 0x00]]);
+
+    // the same packet without its stop bit
+    gb.bus.io_registers.p1.packets.clear();
+    p1(&mut gb, 0x00);
+    p1(&mut gb, 0x30);
+    for bit in 0..128 {
+        p1(&mut gb, if packet[bit / 8] >> (bit % 8) & 0x1 == 1 { 0x10 } else { 0x20 });
+        p1(&mut gb, 0x30);
+    }
+    assert!(gb.bus.io_registers.p1.packets.is_empty(), "packet before the stop bit");
+    p1(&mut gb, 0x20);
+    assert_eq!(gb.bus.io_registers.p1.packets.len(), 1);
+
+    let mut gb = common::start(&common::rom(), Model::Dmg);
+    send_packet(&mut gb, &packet);
+    assert!(gb.bus.io_registers.p1.packets.is_empty(), "packet on DMG");
+}
+
+// PALxx sets colour 0 of every palette and colours 1-3 of its two palettes.
+#[test]
+fn palette_commands() {
+    let colors: [u16; 7] = [0x7C00, 0x0001, 0x0002, 0x0003, 0x0004, 0x0005, 0x0006];
+    let untouched: [u16; 4] = [colors[0], 0x56B5, 0x294A, 0x0000];
+    for (code, first, second) in [(0x01_u8, 0, 1), (0x09, 2, 3), (0x11, 0, 3), (0x19, 1, 2)].iter() {
+        let mut gb = common::start(&common::rom(), Model::Sgb);
+        let mut bytes: Vec<u8> = vec![*code];
+        bytes.extend(colors.iter().flat_map(|c| c.to_le_bytes()));
+        command(&mut gb, &bytes);
+
+        for (i, palette) in sgb(&gb).palettes.iter().enumerate() {
+            let expected: [u16; 4] = if i == *first {
+                [colors[0], colors[1], colors[2], colors[3]]
+            } else if i == *second {
+                [colors[0], colors[4], colors[5], colors[6]]
+            } else {
+                untouched
+            };
+            assert_eq!(*palette, expected, "command 0x{:02X}, palette {}", code, i);
+        }
+        // colour 0 is the backdrop around the screen too
+        let frame: Vec<u8> = sgb(&gb).frame(&gb.ppu.image);
+        assert_eq!(frame[0..3], ColorCorrection::None.rgb(colors[0]));
+    }
+}
+
+// ATTR_BLK in two packets: outside only (with the border), inside only (with the
+// border) and the border alone.
+#[test]
+fn attr_blk() {
+    let mut gb = common::start(&common::rom(), Model::Sgb);
+    command(&mut gb, &[0x22, 3,
+        0b100, 0x10, 0, 0, 9, 9,
+        0b001, 0x02, 2, 2, 4, 4,
+        0b010, 0x0C, 12, 12, 16, 16,
+    ]);
+    let expected: Vec<u8> = attribute_map(|x, y| {
+        let in_square = |x1: usize, x2: usize| (x1..=x2).contains(&x) && (x1..=x2).contains(&y);
+        if in_square(2, 4) {
+            2
+        } else if in_square(12, 16) && !in_square(13, 15) {
+            3
+        } else if in_square(1, 8) {
+            0
+        } else {
+            1
+        }
+    });
+    assert_eq!(sgb(&gb).attributes.to_vec(), expected);
+
+    // all three areas at once
+    command(&mut gb, &[0x21, 1, 0b111, 0x39, 3, 2, 6, 5]);
+    let expected: Vec<u8> = attribute_map(|x, y| {
+        if x > 3 && x < 6 && y > 2 && y < 5 {
+            1
+        } else if (3..=6).contains(&x) && (2..=5).contains(&y) {
+            2
+        } else {
+            3
+        }
+    });
+    assert_eq!(sgb(&gb).attributes.to_vec(), expected);
+}
+
+// ATTR_LIN: row 2 with palette 1, column 5 with palette 3, then row 17 with palette 2.
+#[test]
+fn attr_lin() {
+    let mut gb = common::start(&common::rom(), Model::Sgb);
+    command(&mut gb, &[0x29, 3, 0xA2, 0x65, 0xD1]);
+    let expected: Vec<u8> = attribute_map(|x, y| match (x, y) {
+        (_, 17) => 2,
+        (5, _) => 3,
+        (_, 2) => 1,
+        _ => 0,
+    });
+    assert_eq!(sgb(&gb).attributes.to_vec(), expected);
+}
+
+// ATTR_DIV: palette 2 before the line, 3 on it and 1 after, at column 10 or row 4.
+#[test]
+fn attr_div() {
+    let mut gb = common::start(&common::rom(), Model::Sgb);
+    let split = |position: usize, line: usize| match position.cmp(&line) {
+        std::cmp::Ordering::Less => 2,
+        std::cmp::Ordering::Equal => 3,
+        std::cmp::Ordering::Greater => 1,
+    };
+    command(&mut gb, &[0x31, 0x39, 10]);
+    assert_eq!(sgb(&gb).attributes.to_vec(), attribute_map(|x, _| split(x, 10)));
+    command(&mut gb, &[0x31, 0x79, 4]);
+    assert_eq!(sgb(&gb).attributes.to_vec(), attribute_map(|_, y| split(y, 4)));
+}
+
+// ATTR_CHR: 4 areas per byte from the high bits, wrapping to the next row going
+// right and to the next column going down.
+#[test]
+fn attr_chr() {
+    let mut gb = common::start(&common::rom(), Model::Sgb);
+    command(&mut gb, &[0x39, 18, 0, 6, 0, 0, 0b00011011, 0b11100000]);
+    let mut expected: Vec<u8> = vec![0; 360];
+    for (x, y, palette) in [(18, 0, 0), (19, 0, 1), (0, 1, 2), (1, 1, 3), (2, 1, 3), (3, 1, 2)].iter() {
+        expected[y * 20 + x] = *palette;
+    }
+    assert_eq!(sgb(&gb).attributes.to_vec(), expected);
+
+    command(&mut gb, &[0x39, 0, 16, 4, 0, 1, 0b01010101]);
+    for (x, y) in [(0, 16), (0, 17), (1, 0), (1, 1)].iter() {
+        expected[y * 20 + x] = 1;
+    }
+    assert_eq!(sgb(&gb).attributes.to_vec(), expected);
+}
+
+// MASK_EN keeps the last frame on screen, blacks it out or fills it with colour 0
+// while the game keeps drawing, until it's cancelled.
+#[test]
+fn mask_en() {
+    let mut gb = transfer_machine(&pattern());
+    command(&mut gb, &[0x01, 0xFF, 0x7F, 0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C]);
+    let shown: Vec<[u8; 3]> = screen(&gb);
+
+    command(&mut gb, &[0xB9, 1]);
+    assert_eq!(sgb(&gb).mask, Mask::Freeze);
+    gb.bus.set_byte(0xFF47, 0x1B);
+    common::run_frames(&mut gb, 2);
+    assert_eq!(screen(&gb), shown, "frozen screen changed");
+
+    command(&mut gb, &[0xB9, 2]);
+    assert!(screen(&gb).iter().all(|pixel| *pixel == [0, 0, 0]), "screen not black");
+
+    command(&mut gb, &[0xB9, 3]);
+    assert!(screen(&gb).iter().all(|pixel| *pixel == [255, 255, 255]), "screen not colour 0");
+
+    command(&mut gb, &[0xB9, 0]);
+    assert_eq!(sgb(&gb).mask, Mask::None);
+    let drawn: Vec<[u8; 3]> = screen(&gb);
+    assert_ne!(drawn, shown);
+    assert!(drawn.contains(&[255, 0, 0]) && drawn.contains(&[0, 0, 255]));
+}
+
+// After MLT_REQ, P1 reads 0xFF - the joypad number with neither line selected,
+// and moves on to the next joypad every time the buttons are deselected. The
+// other joypads have nothing pressed.
+#[test]
+fn mlt_req() {
+    let mut gb = common::start(&common::rom(), Model::Sgb);
+    gb.bus.io_registers.p1.a = true;
+    for (request, ids) in [(1_u8, vec![0xFF, 0xFE, 0xFF]), (3, vec![0xFF, 0xFE, 0xFD, 0xFC, 0xFF])].iter() {
+        command(&mut gb, &[0x89, *request]);
+        for (i, id) in ids.iter().enumerate() {
+            p1(&mut gb, 0x30);
+            assert_eq!(gb.bus.get_byte(0xFF00), *id, "MLT_REQ {}, read {}", request, i);
+            p1(&mut gb, 0x10);
+            let buttons: u8 = if *id == 0xFF { 0xDE } else { 0xDF };
+            assert_eq!(gb.bus.get_byte(0xFF00), buttons, "MLT_REQ {}, read {}", request, i);
+        }
+    }
+
+    command(&mut gb, &[0x89, 0]);
+    for _ in 0..3 {
+        p1(&mut gb, 0x30);
+        assert_eq!(gb.bus.get_byte(0xFF00), 0xFF);
+        p1(&mut gb, 0x10);
+        assert_eq!(gb.bus.get_byte(0xFF00), 0xDE);
+    }
+}
+
+// PAL_TRN reads the 512 system palettes from the next frame, PAL_SET picks four of
+// them and lifts the mask.
+#[test]
+fn pal_trn() {
+    let data: Vec<u8> = pattern();
+    let mut gb = transfer_machine(&data);
+    command(&mut gb, &[0x59]);
+    common::run_frames(&mut gb, 1);
+    command(&mut gb, &[0xB9, 2]);
+
+    command(&mut gb, &[0x51, 5, 0, 100, 0, 0x2C, 0x01, 0xFF, 0x01, 0x40]);
+    let color = |id: usize, i: usize| u16::from_le_bytes([data[id * 8 + i * 2], data[id * 8 + i * 2 + 1]]);
+    for (i, id) in [5, 100, 300, 511].iter().enumerate() {
+        let expected: [u16; 4] = [color(5, 0), color(*id, 1), color(*id, 2), color(*id, 3)];
+        assert_eq!(sgb(&gb).palettes[i], expected, "palette {}", i);
+    }
+    assert_eq!(sgb(&gb).mask, Mask::None);
+}
+
+// ATTR_TRN reads the 45 attribute files from the next frame, ATTR_SET applies one
+// and lifts the mask.
+#[test]
+fn attr_trn() {
+    let data: Vec<u8> = pattern();
+    let mut gb = transfer_machine(&data);
+    command(&mut gb, &[0xA9]);
+    common::run_frames(&mut gb, 1);
+    command(&mut gb, &[0xB9, 2]);
+
+    command(&mut gb, &[0xB1, 0x40 | 44]);
+    let expected: Vec<u8> = (0..360).map(|i| data[44 * 90 + i / 4] >> (6 - i % 4 * 2) & 0x3).collect();
+    assert_eq!(sgb(&gb).attributes.to_vec(), expected);
+    assert_eq!(sgb(&gb).mask, Mask::None);
+
+    command(&mut gb, &[0xB1, 3]);
+    let expected: Vec<u8> = (0..360).map(|i| data[3 * 90 + i / 4] >> (6 - i % 4 * 2) & 0x3).collect();
+    assert_eq!(sgb(&gb).attributes.to_vec(), expected);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use gamerust::cpu::registers::RR;
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// arms KEY1, runs STOP and leaves 0xAA at 0xFF80 once running again
+static SWITCH: [u8; 12] = [
+    0x3E, 0x01,     // ld a, $01
+    0xE0, 0x4D,     // ldh [$FF4D], a
+    0x10, 0x00,     // stop
+    0x3E, 0xAA,     // ld a, $AA
+    0xE0, 0x80,     // ldh [$FF80], a
+    0x18, 0xFE,     // jr @
+];
+
+fn switcher(mut rom: Vec<u8>, model: Model) -> Gameboy {
+    rom[0x0100..0x0100 + SWITCH.len()].copy_from_slice(&SWITCH);
+    common::start(&rom, model)
+}
+
+// M-cycles until the next frame
+fn frame_cycles(gb: &mut Gameboy) -> u32 {
+    let frames: u64 = gb.ppu.frames;
+    let mut cycles: u32 = 0;
+    while gb.ppu.frames == frames {
+        gb.tick();
+        cycles += 1;
+    }
+    cycles
+}
+
+// KEY1 bit 0 arms the switch, which STOP carries out: the CPU stops for 2050
+// M-cycles, DIV is reset and the PPU then takes twice as many M-cycles per frame.
+#[test]
+fn stop_switches_speed() {
+    let mut gb = switcher(common::cgb_rom(), Model::Cgb);
+    assert_eq!(gb.bus.get_byte(0xFF4D), 0x7E);
+    while !gb.bus.double_speed {
+        gb.tick();
+    }
+    assert_eq!(gb.bus.get_byte(0xFF4D), 0xFE);
+    assert_eq!(gb.bus.io_registers.div, 0);
+    let mut stopped: u32 = 0;
+    while gb.bus.get_byte(0xFF80) != 0xAA {
+        gb.tick();
+        stopped += 1;
+    }
+    assert!((2050..2050 + 8).contains(&stopped), "stopped for {} M-cycles", stopped);
+
+    frame_cycles(&mut gb);
+    assert_eq!(frame_cycles(&mut gb), 17556 * 2);
+}
+
+// Running the switch again goes back to normal speed.
+#[test]
+fn switch_back() {
+    let mut gb = switcher(common::cgb_rom(), Model::Cgb);
+    common::run_frames(&mut gb, 1);
+    assert!(gb.bus.double_speed);
+    gb.cpu.registers.set_r16(RR::PC, 0x0100);
+    common::run_frames(&mut gb, 1);
+    assert!(!gb.bus.double_speed);
+    assert_eq!(gb.bus.get_byte(0xFF4D), 0x7E);
+    assert_eq!(frame_cycles(&mut gb), 17556);
+}
+
+// Without CGB mode, on DMG or in compatibility mode, KEY1 reads 0xFF and does
+// not arm, STOP is an ordinary STOP.
+#[test]
+fn no_switch_without_cgb_mode() {
+    for model in [Model::Cgb, Model::Dmg].iter() {
+        let mut gb = switcher(common::rom(), *model);
+        common::run_frames(&mut gb, 2);
+        assert!(!gb.bus.double_speed, "{:?}", model);
+        assert!(gb.cpu.halted, "{:?}", model);
+        assert_eq!(gb.bus.get_byte(0xFF4D), 0xFF, "{:?}", model);
+        assert_ne!(gb.bus.get_byte(0xFF80), 0xAA, "{:?}", model);
+    }
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// Runs two frames with the given (Y, X, tile) objects in OAM order. Tile 1 is solid colour 1, tile 2 solid colour 3.
+fn render(objects: &[(u8, u8, u8)]) -> Gameboy {
+    let objects: Vec<(u8, u8, u8, u8)> = objects.iter().map(|(y, x, tile)| (*y, *x, *tile, 0)).collect();
+    render_with(0x93, &objects, |_| {})
+}
+
+// Same with (Y, X, tile, attributes) objects, LCDC and a hook to set up the
+// background. Tile 3 is colour 1 on its top row and colour 3 below, tile 4 is
+// colour 2 with a colour 0 right half.
+fn render_with(lcdc: u8, objects: &[(u8, u8, u8, u8)], setup: impl Fn(&mut Gameboy)) -> Gameboy {
+    let mut gb = common::start(&common::rom(), Model::Dmg);
+    gb.bus.access_checks = false;
+    for row in 0..8 {
+        gb.bus.set_byte(0x8010 + row * 2, 0xFF);
+        gb.bus.set_byte(0x8020 + row * 2, 0xFF);
+        gb.bus.set_byte(0x8021 + row * 2, 0xFF);
+        gb.bus.set_byte(0x8030 + row * 2, 0xFF);
+        gb.bus.set_byte(0x8031 + row * 2, if row == 0 { 0x00 } else { 0xFF });
+        gb.bus.set_byte(0x8041 + row * 2, 0xF0);
+    }
+    for (i, (y, x, tile, attributes)) in objects.iter().enumerate() {
+        let address: u16 = 0xFE00 + i as u16 * 4;
+        gb.bus.set_byte(address, *y);
+        gb.bus.set_byte(address + 1, *x);
+        gb.bus.set_byte(address + 2, *tile);
+        gb.bus.set_byte(address + 3, *attributes);
+    }
+    gb.bus.set_byte(0xFF47, 0xE4);
+    gb.bus.set_byte(0xFF48, 0xE4);
+    gb.bus.set_byte(0xFF49, 0x1B);
+    setup(&mut gb);
+    gb.bus.set_byte(0xFF40, lcdc);
+
+    common::run_frames(&mut gb, 2);
+    gb
+}
+
+fn pixel(gb: &Gameboy, x: usize, y: usize) -> u16 {
+    gb.ppu.image[y * 160 + x]
+}
+
+// On DMG the object with the lower X wins where two overlap, whatever their OAM order.
+#[test]
+fn lower_x_wins_over_oam_order() {
+    // OAM 0 covers x 12-19 in colour 3, OAM 1 covers x 8-15 in colour 1
+    let gb = render(&[(16, 20, 2), (16, 16, 1)]);
+    let colour1: u16 = pixel(&gb, 9, 4);
+    let colour3: u16 = pixel(&gb, 18, 4);
+    assert_ne!(colour1, colour3);
+    assert_eq!(pixel(&gb, 13, 4), colour1, "OAM order won over the lower X");
+}
+
+// Objects partly left of the screen are all due at the first pixel, the lower X
+// has to be fetched first there too.
+#[test]
+fn lower_x_wins_at_left_edge() {
+    // OAM 0 covers x 0-3 in colour 3, OAM 1 covers x 0-1 in colour 1
+    let gb = render(&[(16, 4, 2), (16, 2, 1), (32, 40, 1)]);
+    let colour1: u16 = pixel(&gb, 34, 20);
+    let colour3: u16 = pixel(&gb, 2, 4);
+    assert_ne!(colour1, colour3);
+    assert_eq!(pixel(&gb, 0, 4), colour1, "OAM order won over the lower X");
+}
+
+// With the same X the object first in OAM wins.
+#[test]
+fn equal_x_falls_back_to_oam_order() {
+    // the last two objects show each colour on its own for reference
+    let gb = render(&[(16, 20, 1), (16, 20, 2), (32, 40, 1), (32, 60, 2)]);
+    let colour1: u16 = pixel(&gb, 34, 20);
+    let colour3: u16 = pixel(&gb, 54, 20);
+    assert_ne!(colour1, colour3);
+    assert_eq!(pixel(&gb, 14, 4), colour1, "later OAM entry won with the same X");
+}
+
+// Only 10 objects are drawn per line, the first 10 in OAM order, including ones
+// outside the screen horizontally.
+#[test]
+fn ten_objects_per_line() {
+    let mut objects: Vec<(u8, u8, u8, u8)> = vec![(16, 0, 1, 0), (16, 200, 1, 0)];
+    for i in 0..9 {
+        objects.push((16, 8 + i * 16, 2, 0));
+    }
+    let gb = render_with(0x93, &objects, |_| {});
+    assert_eq!(pixel(&gb, 7 * 16 + 2, 4), 3);
+    assert_eq!(pixel(&gb, 8 * 16 + 2, 4), 0, "an 11th object was drawn");
+}
+
+// 8x16 objects ignore bit 0 of the tile index, Y flip swaps the two tiles.
+#[test]
+fn tall_objects_ignore_tile_bit_0() {
+    let gb = render_with(0x97, &[(16, 8, 3, 0), (16, 24, 2, 0x40)], |_| {});
+    // tile 3 is drawn as tiles 2 (top, colour 3) and 3 (bottom)
+    assert_eq!(pixel(&gb, 2, 0), 3);
+    assert_eq!(pixel(&gb, 2, 8), 1);
+    assert_eq!(pixel(&gb, 2, 9), 3);
+    // flipped: tile 3 upside down on top, then tile 2
+    assert_eq!(pixel(&gb, 18, 6), 3);
+    assert_eq!(pixel(&gb, 18, 7), 1);
+    assert_eq!(pixel(&gb, 18, 8), 3);
+}
+
+// With attribute bit 7 an object only shows over BG colour 0.
+#[test]
+fn background_over_object() {
+    // map entry 0 uses tile 4: colour 2 on the left half, 0 on the right
+    let gb = render_with(0x93, &[(16, 8, 2, 0x80)], |gb| gb.bus.set_byte(0x9800, 4));
+    assert_eq!(pixel(&gb, 1, 2), 2, "object drawn over BG colour 2");
+    assert_eq!(pixel(&gb, 6, 2), 3, "object hidden behind BG colour 0");
+}
+
+// Attribute bit 4 picks OBP1, bit 5 flips horizontally.
+#[test]
+fn palette_and_x_flip() {
+    // tile 4 mirrored: colour 0 on the left half, colour 2 on the right
+    let gb = render_with(0x93, &[(16, 8, 4, 0x30)], |_| {});
+    assert_eq!(pixel(&gb, 1, 2), 0);
+    // OBP1 = 0x1B maps colour 2 to shade 1
+    assert_eq!(pixel(&gb, 6, 2), 1);
+}
+
+// On DMG LCDC bit 0 blanks BG and window, objects are still drawn.
+#[test]
+fn objects_without_background() {
+    let gb = render_with(0x92, &[(16, 8, 2, 0x80)], |gb| gb.bus.set_byte(0x9800, 2));
+    assert_eq!(pixel(&gb, 2, 2), 3, "object hidden behind a disabled background");
+    assert_eq!(pixel(&gb, 12, 2), 0);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// DMG with the LCD on, the given STAT sources enabled and LYC set, from the top
+// of a frame with IF clear
+fn stat_machine(sources: u8, lyc: u8) -> Gameboy {
+    let mut gb = common::start(&common::rom(), Model::Dmg);
+    gb.bus.set_byte(0xFF41, sources);
+    gb.bus.set_byte(0xFF45, lyc);
+    common::run_frames(&mut gb, 1);
+    while !(gb.bus.io_registers.ly == 0 && gb.bus.io_registers.stat & 0b11 == 2) {
+        dot(&mut gb);
+    }
+    gb.bus.set_byte(0xFF0F, 0);
+    gb
+}
+
+// the PPU alone, one dot at a time
+fn dot(gb: &mut Gameboy) {
+    gb.ppu.tick(&mut gb.bus, 1);
+}
+
+// runs `dots` dots and returns the LY and mode of every STAT interrupt request
+fn stat_requests(gb: &mut Gameboy, dots: u32) -> Vec<(u8, u8)> {
+    let mut requests: Vec<(u8, u8)> = Vec::new();
+    for _ in 0..dots {
+        dot(gb);
+        if gb.bus.get_byte(0xFF0F) & 0b10 != 0 {
+            requests.push((gb.bus.io_registers.ly, gb.bus.io_registers.stat & 0b11));
+            gb.bus.set_byte(0xFF0F, 0);
+        }
+    }
+    requests
+}
+
+// With the HBlank and OAM sources on, the line stays high from HBlank into the
+// OAM search of the next line, so after line 0 only HBlank requests an interrupt.
+#[test]
+fn hblank_blocks_oam() {
+    let mut gb = stat_machine(0x28, 0xFF);
+    let requests: Vec<(u8, u8)> = stat_requests(&mut gb, 456 * 4);
+    assert_eq!(requests, vec![(0, 2), (0, 0), (1, 0), (2, 0), (3, 0)]);
+
+    // on its own the OAM source fires at the start of every line
+    let mut gb = stat_machine(0x20, 0xFF);
+    let requests: Vec<(u8, u8)> = stat_requests(&mut gb, 456 * 4);
+    assert_eq!(requests, vec![(0, 2), (1, 2), (2, 2), (3, 2)]);
+}
+
+// An LY = LYC match starting while HBlank holds the line high is blocked, and
+// the HBlank of the matching line is too because the match keeps the line high.
+#[test]
+fn hblank_blocks_lyc() {
+    let mut gb = stat_machine(0x48, 3);
+    let requests: Vec<(u8, u8)> = stat_requests(&mut gb, 456 * 5);
+    assert_eq!(requests, vec![(0, 0), (1, 0), (2, 0), (4, 0)]);
+
+    let mut gb = stat_machine(0x40, 3);
+    assert_eq!(stat_requests(&mut gb, 456 * 5), vec![(3, 2)]);
+}
+
+// At the start of line 144 the VBlank, OAM and LYC sources all rise on the same
+// dot and a single interrupt is requested. VBlank then holds the line high into
+// the OAM search of line 0, the next request is at line 1.
+#[test]
+fn sources_rising_together() {
+    let mut gb = stat_machine(0x70, 144);
+    while !(gb.bus.io_registers.ly == 143 && gb.bus.io_registers.stat & 0b11 == 3) {
+        dot(&mut gb);
+    }
+    gb.bus.set_byte(0xFF0F, 0);
+    let mut requests: Vec<(u8, u8)> = Vec::new();
+    while requests.len() < 2 {
+        requests.extend(stat_requests(&mut gb, 1));
+    }
+    assert_eq!(requests, vec![(144, 1), (1, 2)]);
+}
+
+// LY reads 0 from dot 4 of line 153, and LYC is compared with that: LYC = 153
+// only matches for the first 4 dots and LYC = 0 matches from dot 4 on, without a
+// second interrupt when line 0 starts.
+#[test]
+fn line_153() {
+    for (lyc, matching) in [(153_u8, 0..4), (0, 4..456)].iter() {
+        let mut gb = stat_machine(0x40, *lyc);
+        while gb.bus.io_registers.ly != 153 {
+            dot(&mut gb);
+        }
+        gb.bus.set_byte(0xFF0F, 0);
+        let mut ly: Vec<u8> = Vec::new();
+        for d in 0..456 {
+            dot(&mut gb);
+            ly.push(gb.bus.io_registers.ly);
+            let coincidence: bool = gb.bus.get_byte(0xFF41) & 0b100 != 0;
+            assert_eq!(coincidence, matching.contains(&d), "LYC = {}, dot {}", lyc, d);
+            let requested: bool = gb.bus.get_byte(0xFF0F) & 0b10 != 0;
+            assert_eq!(requested, d == matching.start, "LYC = {}, dot {}", lyc, d);
+            gb.bus.set_byte(0xFF0F, 0);
+        }
+        assert_eq!(ly, [vec![153; 4], vec![0; 452]].concat());
+        assert_eq!(gb.bus.io_registers.ly, 0);
+        assert_eq!(stat_requests(&mut gb, 8), vec![]);
+    }
+}
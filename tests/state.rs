@@ -0,0 +1,155 @@
+mod common;
+
+use gamerust::cpu::registers::RR;
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+use common::run_frames;
+
+fn pattern_rom() -> Vec<u8> {
+    std::fs::read(format!("{}/tests/golden/pattern.gb", env!("CARGO_MANIFEST_DIR"))).unwrap()
+}
+
+fn pattern() -> Gameboy {
+    common::start(&pattern_rom(), Model::Dmg)
+}
+
+// what can be seen of a machine without a save state: CPU registers, LY, STAT,
+// IF, IE, DIV, TIMA, whether OAM DMA runs, HDMA5 and the dots since power on
+fn observe(gb: &Gameboy) -> Vec<u64> {
+    let mut seen: Vec<u64> = [RR::AF, RR::BC, RR::DE, RR::HL, RR::SP, RR::PC].iter()
+        .map(|rr| gb.cpu.registers.get_r16(*rr) as u64)
+        .collect();
+    for address in [0xFF44, 0xFF41, 0xFF0F, 0xFFFF, 0xFF04, 0xFF05, 0xFF55].iter() {
+        seen.push(gb.bus.peek(*address) as u64);
+    }
+    seen.push(gb.bus.dma.active() as u64);
+    seen.push(gb.time);
+    seen
+}
+
+// restores `saved` into a machine that has only just started with `rom`
+fn restore(rom: &[u8], model: Model, saved: &[u8]) -> Gameboy {
+    let mut gb = common::start(rom, model);
+    gb.load_state(saved).unwrap();
+    gb
+}
+
+// A state taken halfway through a line with OAM DMA running, restored into a new
+// machine: both show the same state, then run to the same frame and OAM.
+#[test]
+fn round_trip() {
+    let mut gb = pattern();
+    run_frames(&mut gb, 3);
+    for _ in 0..5000 {
+        gb.tick();
+    }
+    // OAM DMA from the ROM program, 40 of its 160 bytes copied
+    gb.bus.set_byte(0xFF46, 0x01);
+    for _ in 0..41 {
+        gb.tick();
+    }
+    assert!(gb.bus.dma.active());
+    let saved: Vec<u8> = gb.save_state();
+    let before: Vec<u64> = observe(&gb);
+
+    let mut restored: Gameboy = restore(&pattern_rom(), Model::Dmg, &saved);
+    assert_eq!(observe(&restored), before);
+    assert_eq!(restored.save_state(), saved);
+
+    run_frames(&mut gb, 3);
+    run_frames(&mut restored, 3);
+    assert_eq!(observe(&restored), observe(&gb));
+    assert_eq!(restored.ppu.image.to_vec(), gb.ppu.image.to_vec());
+    for address in 0xFE00..0xFEA0 {
+        assert_eq!(restored.bus.peek(address), gb.bus.peek(address), "OAM 0x{:04X}", address);
+    }
+}
+
+// a HBlank DMA saved between blocks carries on with the same blocks after a restore
+#[test]
+fn hdma_progress() {
+    let mut gb = common::cgb();
+    for i in 0..0x40_u16 {
+        gb.bus.set_byte(0xC000 + i, i as u8 + 1);
+    }
+    while gb.bus.io_registers.stat & 0b11 != 3 {
+        gb.tick();
+    }
+    for (address, value) in [(0xFF51, 0xC0), (0xFF52, 0x00), (0xFF53, 0x00), (0xFF54, 0x00), (0xFF55, 0x83)].iter() {
+        gb.bus.set_byte(*address, *value);
+    }
+    while gb.bus.io_registers.stat & 0b11 != 0 {
+        gb.tick();
+    }
+    assert_eq!(gb.bus.get_byte(0xFF55), 0x02);
+    let saved: Vec<u8> = gb.save_state();
+
+    let mut restored: Gameboy = restore(&common::cgb_rom(), Model::Cgb, &saved);
+    assert_eq!(observe(&restored), observe(&gb));
+    assert_eq!(restored.bus.peek(0x800F), 0x10);
+    assert_eq!(restored.bus.peek(0x8010), 0x00);
+
+    run_frames(&mut gb, 1);
+    run_frames(&mut restored, 1);
+    assert_eq!(restored.bus.get_byte(0xFF55), 0xFF);
+    for i in 0..0x40_u16 {
+        assert_eq!(restored.bus.peek(0x8000 + i), i as u8 + 1, "VRAM 0x{:04X}", 0x8000 + i);
+    }
+    assert_eq!(observe(&restored), observe(&gb));
+}
+
+// the SGB border, palettes and attributes come back with the state
+#[test]
+fn sgb_border() {
+    let mut gb = common::start(&common::rom(), Model::Sgb);
+    // the screen holds the transferred data while the LCD is off
+    gb.bus.set_byte(0xFF40, 0x00);
+    gb.tick();
+    let mut seed: u32 = 1;
+    for pixel in gb.ppu.image.iter_mut() {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        *pixel = (seed >> 16) as u16 & 0x3;
+    }
+    // CHR_TRN for tiles 0x00 - 0x7F, then PCT_TRN, a frame apart
+    for command in [0x13 << 3 | 1, 0x14 << 3 | 1].iter() {
+        let mut packet: [u8; 16] = [0; 16];
+        packet[0] = *command;
+        gb.bus.io_registers.p1.packets.push_back(packet);
+        run_frames(&mut gb, 2);
+    }
+    let sgb = gb.sgb.as_ref().unwrap();
+    let frame: Vec<u8> = sgb.frame(&gb.ppu.image);
+    assert!(frame[..256 * 3 * 8].iter().any(|c| *c != 0), "no border was drawn");
+
+    let restored: Gameboy = restore(&common::rom(), Model::Sgb, &gb.save_state());
+    assert_eq!(restored.sgb.as_ref().unwrap().frame(&restored.ppu.image), frame);
+    assert_eq!(observe(&restored), observe(&gb));
+}
+
+// a cut short state is rejected and leaves the running machine alone
+#[test]
+fn truncated_state() {
+    let mut gb = pattern();
+    run_frames(&mut gb, 1);
+    let saved: Vec<u8> = gb.save_state();
+    run_frames(&mut gb, 1);
+    let before: Vec<u8> = gb.save_state();
+    let seen: Vec<u64> = observe(&gb);
+
+    for length in [4, saved.len() / 2, saved.len() - 1].iter() {
+        assert!(gb.load_state(&saved[..*length]).is_err(), "{} bytes were accepted", length);
+        assert_eq!(gb.save_state(), before, "machine changed by a failed load of {} bytes", length);
+        assert_eq!(observe(&gb), seen, "machine changed by a failed load of {} bytes", length);
+    }
+}
+
+#[test]
+fn wrong_rom() {
+    let mut gb = pattern();
+    let mut saved: Vec<u8> = gb.save_state();
+    // the ROM CRC-32 follows the magic and the version
+    saved[6] ^= 0xFF;
+    let error: String = gb.load_state(&saved).unwrap_err();
+    assert!(error.contains("another ROM"), "{}", error);
+}
@@ -0,0 +1,15 @@
+mod common;
+
+use gamerust::model::Model;
+
+// PCMEM shows the bytes at PC even while OAM DMA keeps the CPU off the ROM
+#[test]
+fn pcmem_during_oam_dma() {
+    let mut rom: Vec<u8> = common::rom();
+    rom[0x0100..0x0104].copy_from_slice(&[0x18, 0xFE, 0x12, 0x34]);
+    let mut gb = common::start(&rom, Model::Dmg);
+    gb.bus.set_byte(0xFF46, 0xC0);
+    gb.bus.tick_dma();
+    gb.bus.tick_dma();
+    assert!(gb.cpu.trace_registers(&gb.bus).ends_with("PC:0100 PCMEM:18,FE,12,34"));
+}
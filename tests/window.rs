@@ -0,0 +1,144 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// DMG with tiles 1-3 solid colours 1-3, an empty background and the window map
+// at 0x9C00 using tile 1, 2, 3, 1... for its rows of tiles
+fn window_machine() -> Gameboy {
+    let mut gb = common::start(&common::rom(), Model::Dmg);
+    gb.bus.access_checks = false;
+    for row in 0..8 {
+        gb.bus.set_byte(0x8010 + row * 2, 0xFF);
+        gb.bus.set_byte(0x8021 + row * 2, 0xFF);
+        gb.bus.set_byte(0x8030 + row * 2, 0xFF);
+        gb.bus.set_byte(0x8031 + row * 2, 0xFF);
+    }
+    for i in 0..0x400_u16 {
+        gb.bus.set_byte(0x9C00 + i, (i / 32 % 3) as u8 + 1);
+    }
+    gb.bus.set_byte(0xFF47, 0xE4);
+    gb.bus.set_byte(0xFF4A, 0);
+    gb.bus.set_byte(0xFF4B, 7);
+    // LCD, window map 0x9C00, window, tile data 0x8000, BG
+    gb.bus.set_byte(0xFF40, 0xF1);
+    // start from the top of a frame
+    common::run_frames(&mut gb, 1);
+    gb
+}
+
+// runs until OAM search of line `ly`, before it is drawn
+fn run_to_line(gb: &mut Gameboy, ly: u8) {
+    while !(gb.bus.io_registers.ly == ly && gb.bus.io_registers.stat & 0b11 == 2) {
+        gb.tick();
+    }
+}
+
+fn pixel(gb: &Gameboy, x: usize, y: usize) -> u16 {
+    gb.ppu.image[y * 160 + x]
+}
+
+// Hiding the window with WX for some lines pauses its line counter, it goes on
+// with the next row of the window once shown again (dmg-acid2 checks this).
+#[test]
+fn hidden_lines_do_not_advance_the_window() {
+    let mut gb = window_machine();
+    run_to_line(&mut gb, 8);
+    gb.bus.set_byte(0xFF4B, 0xFF);
+    run_to_line(&mut gb, 16);
+    gb.bus.set_byte(0xFF4B, 7);
+    common::run_frames(&mut gb, 1);
+
+    assert_eq!(pixel(&gb, 80, 4), 1);
+    assert_eq!(pixel(&gb, 80, 12), 0, "window drawn with WX = 0xFF");
+    assert_eq!(pixel(&gb, 80, 20), 2, "window skipped the rows of the hidden lines");
+}
+
+// Same with LCDC bit 5 turned off and on again.
+#[test]
+fn disabled_lines_do_not_advance_the_window() {
+    let mut gb = window_machine();
+    run_to_line(&mut gb, 8);
+    gb.bus.set_byte(0xFF40, 0xD1);
+    run_to_line(&mut gb, 16);
+    gb.bus.set_byte(0xFF40, 0xF1);
+    common::run_frames(&mut gb, 1);
+
+    assert_eq!(pixel(&gb, 80, 4), 1);
+    assert_eq!(pixel(&gb, 80, 12), 0, "window drawn while disabled");
+    assert_eq!(pixel(&gb, 80, 20), 2, "window skipped the rows of the disabled lines");
+}
+
+// Switching the window map between lines takes effect on the next line.
+#[test]
+fn window_map_switch_mid_frame() {
+    let mut gb = window_machine();
+    run_to_line(&mut gb, 8);
+    // back to map 0x9800, which is all tile 0
+    gb.bus.set_byte(0xFF40, 0xB1);
+    common::run_frames(&mut gb, 1);
+
+    assert_eq!(pixel(&gb, 80, 4), 1);
+    assert_eq!(pixel(&gb, 80, 12), 0);
+}
+
+// window_machine with the window from line 4 at `wx`, over a background of colour
+// 1. The window map is all tile 4, whose columns are colours 0, 1, 2, 3, 3, 3, 3, 3.
+fn window_at(wx: u8) -> Gameboy {
+    let mut gb = window_machine();
+    for row in 0..8 {
+        gb.bus.set_byte(0x8040 + row * 2, 0x5F);
+        gb.bus.set_byte(0x8041 + row * 2, 0x3F);
+    }
+    for i in 0..0x400_u16 {
+        gb.bus.set_byte(0x9800 + i, 1);
+        gb.bus.set_byte(0x9C00 + i, 4);
+    }
+    gb.bus.set_byte(0xFF4A, 4);
+    gb.bus.set_byte(0xFF4B, wx);
+    common::run_frames(&mut gb, 1);
+    gb
+}
+
+fn row(gb: &Gameboy, y: usize, from: usize, to: usize) -> Vec<u16> {
+    (from..to).map(|x| pixel(gb, x, y)).collect()
+}
+
+// WX below 7 starts the window at the left edge with its first 7 - WX pixels cut
+// off, the second window tile starts at x = WX + 1.
+#[test]
+fn wx_below_7() {
+    let gb = window_at(7);
+    assert_eq!(row(&gb, 3, 0, 8), vec![1; 8]);
+    assert_eq!(row(&gb, 4, 0, 9), vec![0, 1, 2, 3, 3, 3, 3, 3, 0]);
+
+    let expected: [[u16; 4]; 7] = [
+        [3, 0, 1, 2],
+        [3, 3, 0, 1],
+        [3, 3, 3, 0],
+        [3, 3, 3, 3],
+        [3, 3, 3, 3],
+        [2, 3, 3, 3],
+        [1, 2, 3, 3],
+    ];
+    for (wx, pixels) in expected.iter().enumerate() {
+        let gb = window_at(wx as u8);
+        assert_eq!(row(&gb, 4, 0, 4), pixels.to_vec(), "WX = {}", wx);
+        assert_eq!(row(&gb, 4, wx + 1, wx + 3), vec![0, 1], "WX = {}", wx);
+    }
+}
+
+// WX = 166 shows the first window pixel at x = 159, and the window covers the
+// whole of the following line.
+#[test]
+fn wx_166() {
+    let gb = window_at(166);
+    assert_eq!(row(&gb, 4, 0, 160), [vec![1; 159], vec![0]].concat());
+    assert_eq!(row(&gb, 5, 0, 9), vec![0, 1, 2, 3, 3, 3, 3, 3, 0]);
+    assert_eq!(row(&gb, 5, 152, 160), vec![0, 1, 2, 3, 3, 3, 3, 3]);
+
+    // one column earlier is an ordinary window that ends at the screen edge
+    let gb = window_at(165);
+    assert_eq!(row(&gb, 4, 156, 160), vec![1, 1, 0, 1]);
+    assert_eq!(row(&gb, 5, 0, 8), vec![1; 8]);
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use gamerust::gameboy::Gameboy;
+use gamerust::model::Model;
+
+// writes the bank number to 0xD000 of WRAM banks 1 - 7
+fn fill_banks(gb: &mut Gameboy) {
+    for bank in 1..8 {
+        gb.bus.set_byte(0xFF70, bank);
+        gb.bus.set_byte(0xD000, bank);
+    }
+}
+
+// SVBK maps one of banks 1 - 7 at 0xD000 - 0xDFFF, and its echo at 0xF000.
+// Bank 0 stays at 0xC000.
+#[test]
+fn svbk_switches_banks() {
+    let mut gb = common::cgb();
+    gb.bus.set_byte(0xC000, 0x42);
+    fill_banks(&mut gb);
+    for bank in (1..8).rev() {
+        gb.bus.set_byte(0xFF70, bank);
+        assert_eq!(gb.bus.get_byte(0xFF70), 0xF8 | bank);
+        assert_eq!(gb.bus.get_byte(0xD000), bank);
+        assert_eq!(gb.bus.get_byte(0xF000), bank);
+        assert_eq!(gb.bus.get_byte(0xC000), 0x42);
+    }
+    // only bits 0 - 2 pick the bank
+    gb.bus.set_byte(0xFF70, 0xFB);
+    assert_eq!(gb.bus.get_byte(0xD000), 3);
+}
+
+// Writing 0 selects bank 1.
+#[test]
+fn svbk_0_maps_bank_1() {
+    let mut gb = common::cgb();
+    fill_banks(&mut gb);
+    gb.bus.set_byte(0xFF70, 0);
+    assert_eq!(gb.bus.get_byte(0xFF70), 0xF9);
+    assert_eq!(gb.bus.get_byte(0xD000), 1);
+    gb.bus.set_byte(0xD001, 0x55);
+    gb.bus.set_byte(0xFF70, 1);
+    assert_eq!(gb.bus.get_byte(0xD001), 0x55);
+}
+
+// DMG hardware and DMG cartridges on CGB only have bank 1, SVBK reads 0xFF.
+#[test]
+fn svbk_without_cgb_mode() {
+    for gb in [common::start(&common::rom(), Model::Dmg), common::start(&common::rom(), Model::Cgb)].iter_mut() {
+        gb.bus.set_byte(0xD000, 0x11);
+        gb.bus.set_byte(0xFF70, 2);
+        assert_eq!(gb.bus.get_byte(0xFF70), 0xFF, "{:?}", gb.model);
+        assert_eq!(gb.bus.get_byte(0xD000), 0x11, "{:?}", gb.model);
+    }
+}